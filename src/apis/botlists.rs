@@ -0,0 +1,217 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::AUTHORIZATION;
+use serde_json::{json, Value};
+use serenity::async_trait;
+use serenity::prelude::TypeMap;
+use tokio::sync::RwLock;
+
+use crate::cache::{BotListCache, ConfigCache, StatsManagerCache};
+use crate::config::BotListConfig;
+
+// Bot list sites we post our server count to, and accept votes from. Each one is only
+// enabled if its token is set, see BotListConfig.
+
+static POST_INTERVAL: Duration = Duration::from_secs(60 * 30);
+// how often we check if we're ready to post our first count
+static STARTUP_INTERVAL: Duration = Duration::from_secs(30);
+
+pub type ProviderResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+#[async_trait]
+pub trait BotListProvider: Send + Sync {
+    /// Shown in logs and vote announcements
+    fn name(&self) -> &'static str;
+
+    async fn post_stats(&self, client: &reqwest::Client, bot_id: u64, servers: u64, shards: u64) -> ProviderResult;
+
+    /// The path our webhook server accepts votes from this site on
+    fn vote_path(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// What the site sends in the authorization header of its vote webhooks
+    fn vote_secret(&self) -> Option<&str> {
+        None
+    }
+
+    /// Pulls the voting user's id out of a vote webhook's body
+    fn parse_vote(&self, _body: &Value) -> Option<u64> {
+        None
+    }
+}
+
+// user ids are sent as strings by most sites
+fn snowflake(value: Option<&Value>) -> Option<u64> {
+    match value? {
+        Value::String(s) => s.parse::<u64>().ok(),
+        Value::Number(n) => n.as_u64(),
+        _ => None,
+    }
+}
+
+/** top.gg **/
+pub struct TopGg {
+    client: dbl::Client,
+    webhook_password: Option<String>,
+}
+
+#[async_trait]
+impl BotListProvider for TopGg {
+    fn name(&self) -> &'static str {
+        "top.gg"
+    }
+
+    async fn post_stats(&self, _client: &reqwest::Client, bot_id: u64, servers: u64, shards: u64) -> ProviderResult {
+        let stats = dbl::types::ShardStats::Cumulative {
+            server_count: servers,
+            shard_count: Some(shards),
+        };
+        self.client.update_stats(bot_id, stats).await?;
+        Ok(())
+    }
+
+    fn vote_path(&self) -> Option<&'static str> {
+        Some("dblwebhook")
+    }
+
+    fn vote_secret(&self) -> Option<&str> {
+        self.webhook_password.as_deref()
+    }
+
+    fn parse_vote(&self, body: &Value) -> Option<u64> {
+        snowflake(body.get("user"))
+    }
+}
+
+/** discord.bots.gg **/
+pub struct DiscordBotsGg {
+    token: String,
+}
+
+#[async_trait]
+impl BotListProvider for DiscordBotsGg {
+    fn name(&self) -> &'static str {
+        "discord.bots.gg"
+    }
+
+    async fn post_stats(&self, client: &reqwest::Client, bot_id: u64, servers: u64, shards: u64) -> ProviderResult {
+        client
+            .post(&format!("https://discord.bots.gg/api/v1/bots/{}/stats", bot_id))
+            .header(AUTHORIZATION, &self.token)
+            .json(&json!({ "guildCount": servers, "shardCount": shards }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/** discordbotlist.com **/
+pub struct DiscordBotList {
+    token: String,
+    webhook_password: Option<String>,
+}
+
+#[async_trait]
+impl BotListProvider for DiscordBotList {
+    fn name(&self) -> &'static str {
+        "discordbotlist.com"
+    }
+
+    async fn post_stats(&self, client: &reqwest::Client, bot_id: u64, servers: u64, _shards: u64) -> ProviderResult {
+        client
+            .post(&format!("https://discordbotlist.com/api/v1/bots/{}/stats", bot_id))
+            .header(AUTHORIZATION, format!("Bot {}", self.token))
+            .json(&json!({ "guilds": servers }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn vote_path(&self) -> Option<&'static str> {
+        Some("dblcomwebhook")
+    }
+
+    fn vote_secret(&self) -> Option<&str> {
+        self.webhook_password.as_deref()
+    }
+
+    fn parse_vote(&self, body: &Value) -> Option<u64> {
+        snowflake(body.get("id"))
+    }
+}
+
+// Builds every provider we have a token for
+pub fn providers_from_config(config: &BotListConfig) -> Vec<Box<dyn BotListProvider>> {
+    let mut providers: Vec<Box<dyn BotListProvider>> = Vec::new();
+
+    if let Some(token) = &config.dbl_token {
+        match dbl::Client::new(token.clone()) {
+            Ok(client) => providers.push(Box::new(TopGg {
+                client,
+                webhook_password: config.dbl_webhook_password.clone(),
+            })),
+            Err(e) => warn!("Unable to create top.gg client: {}", e),
+        }
+    }
+
+    if let Some(token) = &config.dbotsgg_token {
+        providers.push(Box::new(DiscordBotsGg { token: token.clone() }));
+    }
+
+    if let Some(token) = &config.discordbotlist_token {
+        providers.push(Box::new(DiscordBotList {
+            token: token.clone(),
+            webhook_password: config.discordbotlist_webhook_password.clone(),
+        }));
+    }
+
+    providers
+}
+
+// Whether any provider we'd build has a secret to verify its votes with
+pub fn accepts_votes(config: &BotListConfig) -> bool {
+    (config.dbl_token.is_some() && config.dbl_webhook_password.is_some())
+        || (config.discordbotlist_token.is_some() && config.discordbotlist_webhook_password.is_some())
+}
+
+// Posts our server count to every provider on a schedule
+pub fn spawn_poster(data: Arc<RwLock<TypeMap>>) {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        loop {
+            let delay = if post_all(&client, &data).await {
+                POST_INTERVAL
+            } else {
+                STARTUP_INTERVAL
+            };
+            tokio::time::sleep(delay).await;
+        }
+    });
+}
+
+// Returns false if our shards haven't all reported in yet
+async fn post_all(client: &reqwest::Client, data: &Arc<RwLock<TypeMap>>) -> bool {
+    let (providers, bot_id, servers, shards) = {
+        let read = data.read().await;
+        let stats = read.get::<StatsManagerCache>().unwrap().lock().await;
+        if !stats.is_ready() {
+            return false;
+        }
+
+        let bot_id = read.get::<ConfigCache>().unwrap().read().await.runtime.bot_id;
+        (read.get::<BotListCache>().unwrap().clone(), bot_id, stats.server_count(), stats.shard_count())
+    };
+
+    for provider in providers.iter() {
+        match provider.post_stats(client, bot_id, servers, shards).await {
+            Ok(()) => debug!("Posted {} servers to {}", servers, provider.name()),
+            Err(e) => warn!("Failed to post stats to {}: {}", provider.name(), e),
+        }
+    }
+    true
+}
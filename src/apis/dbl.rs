@@ -1,7 +1,9 @@
-use std::env;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 
 use serenity::{http::Http, prelude::TypeMap};
 use warp::{
@@ -10,106 +12,233 @@ use warp::{
     {path, Filter, Rejection, Reply},
 };
 
-use dbl::types::Webhook;
 use futures_util::future;
+use sha2::{Digest, Sha256};
 
-use crate::cache::DblCache;
+use crate::cache::{BotListCache, MetricsCache, StatsManagerCache, MessageCache, GodboltCache, AliasCache, VoteCache};
+use crate::apis::botlists;
+use crate::config::{BotListConfig, LogConfig, WebhookConfig};
 use crate::utls::discordhelpers;
 use crate::utls::discordhelpers::embeds::*;
 
+// vote payloads are tiny, anything bigger than this isn't from a bot list
+static MAX_BODY_SIZE: u64 = 1024 * 16;
+// bot lists retry deliveries they think failed, we ignore the same vote arriving this soon
+static VOTE_DEDUP_WINDOW: Duration = Duration::from_secs(60 * 5);
+
 pub struct BotsListApi {
-    password: String,
+    address: IpAddr,
     port: u16,
-    vote_channel: u64,
+    // votes are recorded either way, this is just where we announce them
+    vote_channel: Option<u64>,
+    votes: bool,
+    metrics: bool,
 }
 
 impl BotsListApi {
-    pub fn new() -> BotsListApi {
-        let webhookpass = env::var("DBL_WEBHOOK_PASSWORD").unwrap_or_default();
-        let webhookport = env::var("DBL_WEBHOOK_PORT").unwrap_or_default();
-        let port = webhookport.parse::<u16>().unwrap_or_default();
-        let vote_channel = env::var("VOTE_CHANNEL").unwrap_or_default();
-        let channel_id = vote_channel.parse::<u64>().unwrap_or_default();
-
+    pub fn new(webhook: &WebhookConfig, logs: &LogConfig, botlists: &BotListConfig) -> BotsListApi {
         BotsListApi {
-            password: webhookpass,
-            port,
-            vote_channel: channel_id,
+            address: webhook.address,
+            port: webhook.port,
+            vote_channel: logs.vote,
+            votes: botlists::accepts_votes(botlists),
+            metrics: webhook.metrics,
         }
     }
 
     pub fn should_spawn(&self) -> bool {
-        self.port != 0 && !self.password.is_empty() && self.vote_channel != 0
+        self.port != 0 && (self.votes || self.metrics)
     }
 
     pub fn spawn(self, http: Arc<Http>, data: Arc<RwLock<TypeMap>>) {
         tokio::spawn(async move {
-            BotsListApi::start_webhook(
-                http,
-                data,
-                self.vote_channel,
-                self.password.clone(),
-                self.port,
-            )
-            .await
+            let routes = BotsListApi::routes(http, data, self.votes, self.vote_channel, self.metrics);
+
+            info!("Starting webhook on {}:{}", self.address, self.port);
+            warp::serve(routes).run((self.address, self.port)).await
         });
     }
 
-    async fn start_webhook(
+    // Everything our webhook server answers to, kept separate from spawn so it can be driven directly
+    pub fn routes(
         http: Arc<Http>,
         data: Arc<RwLock<TypeMap>>,
-        vote_channel: u64,
-        pass: String,
-        port: u16,
-    ) {
-        let filter = warp::header::<String>("authorization")
-            .and_then(move |value| {
-                if value == pass {
-                    future::ok(())
-                } else {
-                    future::err(warp::reject::custom(Unauthorized))
-                }
-            })
-            .untuple_one();
-
-        let webhook = warp::post()
-            .and(path!("dblwebhook"))
-            .and(filter)
+        votes_enabled: bool,
+        vote_channel: Option<u64>,
+        metrics_enabled: bool,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        // paths are matched before methods, otherwise warp answers 405 instead of 404 for
+        // paths that only exist on another route
+        let vote_data = data.clone();
+        let recent_votes = Arc::new(Mutex::new(RecentVotes::default()));
+        let webhook = vote_endpoint(data.clone())
+            .and(route_enabled(votes_enabled))
+            .and(warp::post())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::body::content_length_limit(MAX_BODY_SIZE))
             .and(warp::body::json())
-            .map(move |hook: Webhook| {
-                let user_id = hook.user.0;
-                let data = data.clone();
-                let http: Arc<Http> = http.clone();
-                BotsListApi::send_vote(user_id, vote_channel, http, data);
+            .and_then(move |endpoint: String, auth: Option<String>, body: serde_json::Value| {
+                BotsListApi::receive_vote(endpoint, auth, body, vote_channel, http.clone(), vote_data.clone(), recent_votes.clone())
+            });
 
-                warp::reply()
-            })
-            .recover(custom_error);
+        let metrics_data = data.clone();
+        let metrics = path!("metrics")
+            .and(warp::get())
+            .and(route_enabled(metrics_enabled))
+            .and_then(move || BotsListApi::render_metrics(metrics_data.clone()));
 
-        info!("Starting webhook");
-        warp::serve(webhook).run(([0, 0, 0, 0], port)).await;
+        let health = path!("health")
+            .and(warp::get())
+            .and_then(move || BotsListApi::health(data.clone()));
+
+        webhook.or(metrics).or(health).recover(custom_error)
     }
 
-    fn send_vote(user_id: u64, vote_channel: u64, http: Arc<Http>, data: Arc<RwLock<TypeMap>>) {
-        tokio::spawn(async move {
+    async fn receive_vote(
+        endpoint: String,
+        auth: Option<String>,
+        body: serde_json::Value,
+        vote_channel: Option<u64>,
+        http: Arc<Http>,
+        data: Arc<RwLock<TypeMap>>,
+        recent_votes: Arc<Mutex<RecentVotes>>,
+    ) -> Result<impl Reply, Rejection> {
+        let (site, user_id) = {
             let read = data.read().await;
-            let client_lock = read.get::<DblCache>().expect("Unable to find dbl data");
-            let awd = client_lock.read().await;
+            let providers = read.get::<BotListCache>().unwrap();
+            let provider = match providers.iter().find(|p| p.vote_path() == Some(endpoint.as_str())) {
+                Some(p) => p,
+                None => return Err(warp::reject::not_found()),
+            };
+
+            // a provider without a secret can't have its votes verified
+            match (provider.vote_secret(), auth) {
+                (Some(secret), Some(auth)) if constant_time_eq(secret.as_bytes(), auth.as_bytes()) => (),
+                _ => return Err(warp::reject::custom(Unauthorized)),
+            }
+
+            match provider.parse_vote(&body) {
+                Some(user_id) => (provider.name(), user_id),
+                None => return Err(warp::reject::custom(InvalidVote)),
+            }
+        };
+
+        // still a success, otherwise the site keeps retrying
+        if !recent_votes.lock().await.insert(site, user_id) {
+            debug!("Ignoring repeated vote from {} on {}", user_id, site);
+            return Ok(warp::reply());
+        }
+
+        data.read().await.get::<VoteCache>().unwrap().write().await.record(user_id);
+        if let Some(channel) = vote_channel {
+            BotsListApi::send_vote(user_id, site, channel, http);
+        }
+        Ok(warp::reply())
+    }
+
+    async fn render_metrics(data: Arc<RwLock<TypeMap>>) -> Result<impl Reply, Rejection> {
+        let read = data.read().await;
+        let metrics = read.get::<MetricsCache>().unwrap();
+
+        // gauges are read at scrape time from whichever of our caches are loaded
+        if let Some(stats) = read.get::<StatsManagerCache>() {
+            let stats = stats.lock().await;
+            metrics.set_guilds(stats.server_count(), stats.shard_count());
+        }
+        if let Some(messages) = read.get::<MessageCache>() {
+            metrics.set_cache_entries("messages", messages.lock().await.len());
+        }
+        if let Some(godbolt) = read.get::<GodboltCache>() {
+            metrics.set_cache_entries("godbolt_languages", godbolt.read().await.cache.len());
+        }
+        if let Some(aliases) = read.get::<AliasCache>() {
+            metrics.set_cache_entries("aliases", aliases.read().await.count());
+        }
+
+        Ok(warp::reply::with_header(
+            metrics.render(),
+            "content-type",
+            "text/plain; version=0.0.4",
+        ))
+    }
 
-            let usr = match awd.user(user_id).await {
+    async fn health(data: Arc<RwLock<TypeMap>>) -> Result<impl Reply, Rejection> {
+        let read = data.read().await;
+        let stats = read.get::<StatsManagerCache>().unwrap().lock().await;
+        Ok(warp::reply::json(&serde_json::json!({
+            "status": "ok",
+            "ready": stats.is_ready(),
+            "guilds": stats.server_count(),
+            "shards": stats.shard_count(),
+        })))
+    }
+
+    fn send_vote(user_id: u64, site: &'static str, vote_channel: u64, http: Arc<Http>) {
+        tokio::spawn(async move {
+            let usr = match http.get_user(user_id).await {
                 Ok(u) => u,
                 Err(err) => return warn!("Unable to retrieve user info: {}", err),
             };
 
-            let tag = format!("{}#{}", usr.username, usr.discriminator);
-            let emb = build_dblvote_embed(tag);
+            let emb = build_dblvote_embed(usr.tag(), site);
             discordhelpers::manual_dispatch(http.clone(), vote_channel, emb).await;
         });
     }
 }
 
+// Votes we've accepted recently, keyed by site and user
+#[derive(Default)]
+struct RecentVotes {
+    votes: HashMap<(&'static str, u64), Instant>,
+}
+
+impl RecentVotes {
+    // returns false if we've already seen this vote
+    fn insert(&mut self, site: &'static str, user_id: u64) -> bool {
+        let now = Instant::now();
+        self.votes.retain(|_, seen| now.duration_since(*seen) < VOTE_DEDUP_WINDOW);
+        self.votes.insert((site, user_id), now).is_none()
+    }
+}
+
+// Compares secrets without leaking how much of them matched through timing. We compare digests
+// so the secret's length doesn't show either.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Each bot list posts votes to its own path, see BotListProvider::vote_path
+fn vote_endpoint(data: Arc<RwLock<TypeMap>>) -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    path!(String).and_then(move |endpoint: String| {
+        let data = data.clone();
+        async move {
+            let read = data.read().await;
+            let providers = read.get::<BotListCache>().unwrap();
+            if providers.iter().any(|p| p.vote_path() == Some(endpoint.as_str())) {
+                Ok(endpoint)
+            } else {
+                Err(warp::reject::not_found())
+            }
+        }
+    })
+}
+
+// Routes we haven't been configured for act like they don't exist
+fn route_enabled(enabled: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::any()
+        .and_then(move || {
+            if enabled {
+                future::ok(())
+            } else {
+                future::err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
 async fn custom_error(err: Rejection) -> Result<impl Reply, Rejection> {
-    if err.find::<BodyDeserializeError>().is_some() {
+    if err.find::<BodyDeserializeError>().is_some() || err.find::<InvalidVote>().is_some() {
         Ok(warp::reply::with_status(
             warp::reply(),
             StatusCode::BAD_REQUEST,
@@ -136,3 +265,172 @@ impl std::fmt::Display for Unauthorized {
 }
 
 impl std::error::Error for Unauthorized {}
+
+#[derive(Debug)]
+struct InvalidVote;
+
+impl warp::reject::Reject for InvalidVote {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::{json, Value};
+    use serenity::async_trait;
+
+    use crate::apis::botlists::{BotListProvider, ProviderResult};
+    use crate::config::StatsConfig;
+    use crate::stats::metrics::Metrics;
+    use crate::stats::statsmanager::StatsManager;
+    use crate::utls::votes::{VotePerks, VoteTracker};
+
+    struct TestList;
+
+    #[async_trait]
+    impl BotListProvider for TestList {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        async fn post_stats(&self, _client: &reqwest::Client, _bot_id: u64, _servers: u64, _shards: u64) -> ProviderResult {
+            Ok(())
+        }
+
+        fn vote_path(&self) -> Option<&'static str> {
+            Some("testwebhook")
+        }
+
+        fn vote_secret(&self) -> Option<&str> {
+            Some("secret")
+        }
+
+        fn parse_vote(&self, body: &Value) -> Option<u64> {
+            body.get("user")?.as_str()?.parse().ok()
+        }
+    }
+
+    // just the caches our routes read, votes are kept out of the working directory
+    fn test_data(name: &str) -> Arc<RwLock<TypeMap>> {
+        let path = std::env::temp_dir().join(format!("votes-{}-{}.json", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+
+        let mut data = TypeMap::new();
+        let providers: Vec<Box<dyn BotListProvider>> = vec![Box::new(TestList)];
+        data.insert::<BotListCache>(Arc::new(providers));
+        data.insert::<VoteCache>(Arc::new(RwLock::new(VoteTracker::load(path.to_str().unwrap(), VotePerks::default()))));
+        data.insert::<StatsManagerCache>(Arc::new(Mutex::new(StatsManager::new(&StatsConfig::default()))));
+        data.insert::<MetricsCache>(Arc::new(Metrics::new()));
+        Arc::new(RwLock::new(data))
+    }
+
+    fn test_routes(data: &Arc<RwLock<TypeMap>>, metrics: bool) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        BotsListApi::routes(Arc::new(Http::new_with_token("")), data.clone(), true, None, metrics)
+    }
+
+    async fn total_votes(data: &Arc<RwLock<TypeMap>>, user: u64) -> u32 {
+        let read = data.read().await;
+        let votes = read.get::<VoteCache>().unwrap().read().await;
+        votes.get(user).map(|r| r.total).unwrap_or(0)
+    }
+
+    #[test]
+    fn secret_comparison() {
+        let cases : &[(&str, &str, bool)] = &[
+            ("secret", "secret", true),
+            ("secret", "secreT", false),
+            ("secret", "secrets", false),
+            ("secret", "", false),
+            ("", "", true),
+        ];
+        for (a, b, equal) in cases {
+            assert_eq!(constant_time_eq(a.as_bytes(), b.as_bytes()), *equal, "{:?} {:?}", a, b);
+        }
+    }
+
+    #[tokio::test]
+    async fn vote_auth() {
+        let data = test_data("auth");
+        let routes = test_routes(&data, false);
+        let vote = json!({ "user": "42" });
+
+        let missing = warp::test::request().method("POST").path("/testwebhook")
+            .json(&vote).reply(&routes).await;
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+
+        let wrong = warp::test::request().method("POST").path("/testwebhook")
+            .header("authorization", "hunter2").json(&vote).reply(&routes).await;
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(total_votes(&data, 42).await, 0);
+
+        let ok = warp::test::request().method("POST").path("/testwebhook")
+            .header("authorization", "secret").json(&vote).reply(&routes).await;
+        assert_eq!(ok.status(), StatusCode::OK);
+        assert_eq!(total_votes(&data, 42).await, 1);
+    }
+
+    #[tokio::test]
+    async fn vote_body_limits() {
+        let data = test_data("limits");
+        let routes = test_routes(&data, false);
+
+        let oversized = json!({ "user": "42", "padding": "x".repeat(MAX_BODY_SIZE as usize) });
+        let res = warp::test::request().method("POST").path("/testwebhook")
+            .header("authorization", "secret").json(&oversized).reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let res = warp::test::request().method("POST").path("/testwebhook")
+            .header("authorization", "secret").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::LENGTH_REQUIRED);
+
+        assert_eq!(total_votes(&data, 42).await, 0);
+    }
+
+    #[tokio::test]
+    async fn duplicate_votes_ignored() {
+        let data = test_data("duplicate");
+        let routes = test_routes(&data, false);
+
+        for _ in 0..2 {
+            let res = warp::test::request().method("POST").path("/testwebhook")
+                .header("authorization", "secret").json(&json!({ "user": "42" })).reply(&routes).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        assert_eq!(total_votes(&data, 42).await, 1);
+    }
+
+    #[tokio::test]
+    async fn unknown_paths() {
+        let data = test_data("unknown");
+        let routes = test_routes(&data, false);
+
+        let res = warp::test::request().method("POST").path("/otherwebhook")
+            .header("authorization", "secret").json(&json!({ "user": "42" })).reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = warp::test::request().path("/nothing/here").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn health() {
+        let data = test_data("health");
+        let res = warp::test::request().path("/health").reply(&test_routes(&data, false)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["ready"], false);
+    }
+
+    #[tokio::test]
+    async fn metrics_only_when_enabled() {
+        let data = test_data("metrics");
+
+        let res = warp::test::request().path("/metrics").reply(&test_routes(&data, false)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = warp::test::request().path("/metrics").reply(&test_routes(&data, true)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(String::from_utf8_lossy(res.body()).contains("guilds"));
+    }
+}
@@ -3,46 +3,242 @@ use serenity::framework::standard::CommandError;
 use serenity::builder::{CreateEmbed};
 use serenity::client::Context;
 use serenity::model::channel::Message;
-use crate::cache::{ConfigCache, GodboltCache};
+use crate::cache::{ConfigCache, GodboltCache, AliasCache, MetricsCache, StatsDatabaseCache, VoteCache};
+use std::time::Instant;
 use serenity::model::user::User;
-use godbolt::{Godbolt, CompilationFilters};
+use godbolt::{Godbolt, CompilationFilters, CompilationResult};
 use crate::utls::parser::ParserResult;
 use crate::utls::discordhelpers::embeds;
+use crate::utls::concurrency::Backend;
+
+use reqwest::header::{ACCEPT, USER_AGENT};
+use serde::*;
+
+/** Compiler Explorer client state **/
+// These mirror the `ClientState` objects godbolt.org hands out for short links, only the
+// parts we know how to replay are kept around.
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ClientState {
+    #[serde(default)]
+    pub sessions: Vec<ClientStateSession>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ClientStateSession {
+    #[serde(default)]
+    pub id: u32,
+    #[serde(default)]
+    pub language: String,
+    #[serde(default)]
+    pub source: String,
+    #[serde(default)]
+    pub compilers: Vec<ClientStateCompiler>,
+    #[serde(default)]
+    pub executors: Vec<ClientStateExecutor>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ClientStateCompiler {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub options: String,
+    #[serde(default)]
+    pub libs: Vec<ClientStateLibrary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filters: Option<ClientStateFilters>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientStateFilters {
+    pub binary: bool,
+    pub comment_only: bool,
+    pub demangle: bool,
+    pub directives: bool,
+    pub execute: bool,
+    pub intel: bool,
+    pub labels: bool,
+    pub library_code: bool,
+    pub trim: bool,
+}
+
+impl ClientStateFilters {
+    pub fn from_filters(filters : &CompilationFilters) -> ClientStateFilters {
+        ClientStateFilters {
+            binary: filters.binary.unwrap_or_default(),
+            comment_only: filters.comment_only.unwrap_or_default(),
+            demangle: filters.demangle.unwrap_or_default(),
+            directives: filters.directives.unwrap_or_default(),
+            execute: filters.execute.unwrap_or_default(),
+            intel: filters.intel.unwrap_or_default(),
+            labels: filters.labels.unwrap_or_default(),
+            library_code: filters.library_code.unwrap_or_default(),
+            trim: filters.trim.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ClientStateExecutor {
+    #[serde(default)]
+    pub arguments: String,
+    #[serde(default)]
+    pub stdin: String,
+    #[serde(default)]
+    pub compiler: ClientStateCompiler,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ClientStateLibrary {
+    #[serde(alias = "id")]
+    pub name: String,
+    #[serde(alias = "version")]
+    pub ver: String,
+}
+
+pub async fn get_client_state(link_id : &str) -> Result<ClientState, CommandError> {
+    let url = format!("https://godbolt.org/api/shortlinkinfo/{}", link_id);
+    let client = reqwest::Client::new();
+    let response = match client
+        .get(&url)
+        .header(USER_AGENT, "discord-compiler-bot")
+        .header(ACCEPT, "application/json")
+        .send()
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(CommandError::from(format!("Unable to reach godbolt.org: {}", e)))
+        }
+    };
+
+    if !response.status().is_success() {
+        return Err(CommandError::from(format!(
+            "Unable to find Compiler Explorer link '{}'",
+            link_id
+        )));
+    }
+
+    match response.json::<ClientState>().await {
+        Ok(state) => Ok(state),
+        Err(e) => Err(CommandError::from(format!("Unable to read Compiler Explorer link: {}", e))),
+    }
+}
+
+#[derive(Deserialize)]
+struct ShortenerResponse {
+    url: String,
+}
+
+// Creates a godbolt.org/z/ link for a compilation so users can pick up where we left off
+pub async fn create_short_link(state : &ClientState) -> Result<String, reqwest::Error> {
+    let response = reqwest::Client::new()
+        .post("https://godbolt.org/api/shortener")
+        .header(USER_AGENT, "discord-compiler-bot")
+        .header(ACCEPT, "application/json")
+        .json(state)
+        .send()
+        .await?
+        .json::<ShortenerResponse>()
+        .await?;
+
+    Ok(response.url)
+}
+
+#[derive(Serialize)]
+struct LibraryRequest<'a> {
+    source: &'a str,
+    options: LibraryRequestOptions<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LibraryRequestOptions<'a> {
+    user_arguments: &'a str,
+    filters: &'a CompilationFilters,
+    libraries: Vec<LibraryRequestEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct LibraryRequestEntry<'a> {
+    id: &'a str,
+    version: &'a str,
+}
+
+// godbolt-rs has no notion of libraries, so requests recovered from a short link that use
+// any are sent by hand. The response is the same as any other compilation.
+async fn send_library_request(
+    compiler_id : &str,
+    source : &str,
+    options : &str,
+    filters : &CompilationFilters,
+    libraries : &[ClientStateLibrary]
+) -> Result<CompilationResult, reqwest::Error> {
+    let request = LibraryRequest {
+        source,
+        options: LibraryRequestOptions {
+            user_arguments: options,
+            filters,
+            libraries: libraries
+                .iter()
+                .map(|l| LibraryRequestEntry { id: &l.name, version: &l.ver })
+                .collect(),
+        },
+    };
+
+    let url = format!("https://godbolt.org/api/compiler/{}/compile", compiler_id);
+    reqwest::Client::new()
+        .post(&url)
+        .header(USER_AGENT, "discord-compiler-bot")
+        .header(ACCEPT, "application/json")
+        .json(&request)
+        .send()
+        .await?
+        .json::<CompilationResult>()
+        .await
+}
 
 pub async fn send_request(ctx : Context, mut content : String, author : User, msg : &Message) -> Result<CreateEmbed, CommandError> {
     let data_read = ctx.data.read().await;
     let loading_id;
     let loading_name;
+    let mut max_attachment_size;
+    let url_allow_list;
     {
-        let botinfo_lock = data_read.get::<ConfigCache>().unwrap();
-        let botinfo = botinfo_lock.read().await;
-        loading_id = botinfo
-            .get("LOADING_EMOJI_ID")
-            .unwrap()
-            .clone()
-            .parse::<u64>()
-            .unwrap();
-        loading_name = botinfo.get("LOADING_EMOJI_NAME").unwrap().clone();
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        loading_id = config.emoji.loading_id;
+        loading_name = config.emoji.loading_name.clone();
+        max_attachment_size = config.limits.attachment_bytes();
+        url_allow_list = config.limits.url_allow_list.clone();
     }
 
-    // Try to load in an attachment
-    let attached = parser::get_message_attachment(&msg).await?;
-    if !attached.is_empty() {
-        content.push_str(&format!("\n```\n{}\n```\n", attached));
+    // recent voters can attach larger files
+    max_attachment_size = data_read.get::<VoteCache>().unwrap().read().await.attachment_limit(author.id.0, max_attachment_size);
+
+    // Try to load in our attachments
+    let attachments = parser::get_message_attachments(&msg, max_attachment_size).await?;
+    if !attachments.files.is_empty() {
+        content.push_str(&format!("\n```\n{}\n```\n", attachments.code()));
     }
 
     // parse user input
     let godbolt_lock = data_read.get::<GodboltCache>().unwrap();
-    let result: ParserResult = match parser::get_components(&content, &author, godbolt_lock, &msg.referenced_message).await {
-        Ok(r) => r,
-        Err(e) => {
-            return Err(CommandError::from(format!("{}", e)));
+    let guild_id = msg.guild_id.map(|g| g.0);
+    let result: ParserResult = {
+        let aliases = data_read.get::<AliasCache>().unwrap().read().await;
+        match parser::get_components(&content, &author, godbolt_lock, &msg.referenced_message, max_attachment_size, &attachments.filenames(), &aliases, guild_id, &url_allow_list).await {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(CommandError::from(format!("{}", e)));
+            }
         }
     };
 
     let godbolt = godbolt_lock.read().await;
     let c = match godbolt.resolve(&result.target) {
-        Some(c) => c,
+        Some(c) => c.clone(),
         None => {
             return Err(CommandError::from(format!(
                 "Unable to find valid compiler or language '{}'\n",
@@ -51,6 +247,21 @@ pub async fn send_request(ctx : Context, mut content : String, author : User, ms
         }
     };
 
+    // the language is needed to build a short link later on
+    let mut language = String::new();
+    for cache_entry in &godbolt.cache {
+        if cache_entry.compilers.iter().any(|compiler| compiler.id == c.id) {
+            language = cache_entry.language.id.clone();
+            break;
+        }
+    }
+
+    // we're done with the compiler list, no need to hold up a refresh while we wait in line
+    drop(godbolt);
+
+    // wait for a free slot, our ticket holds it until we're done
+    let _ticket = discordhelpers::wait_in_queue(&ctx, msg, &author, Backend::Godbolt).await?;
+
     // send out loading emote
     let reaction = match msg
         .react(
@@ -77,8 +288,20 @@ pub async fn send_request(ctx : Context, mut content : String, author : User, ms
         trim: Some(true),
     };
 
+    let options = result.options.join(" ");
+    let started = Instant::now();
+    let response = if result.libraries.is_empty() {
+        Godbolt::send_request(&c, &result.code, &options, &filters)
+            .await
+            .map_err(|e| e.to_string())
+    } else {
+        send_library_request(&c.id, &result.code, &options, &filters, &result.libraries)
+            .await
+            .map_err(|e| e.to_string())
+    };
+
     let response =
-        match Godbolt::send_request(&c, &result.code, &result.options.join(" "), &filters).await {
+        match response {
             Ok(resp) => resp,
             Err(e) => {
                 // we failed, lets remove the loading react before leaving so it doesn't seem like we're still processing
@@ -104,5 +327,38 @@ pub async fn send_request(ctx : Context, mut content : String, author : User, ms
         }
     }
 
-    Ok(embeds::build_asm_embed(&author, &response))
+    let metrics = data_read.get::<MetricsCache>().unwrap();
+    metrics.compilation("godbolt", &language, response.code != 0, started.elapsed());
+    if let Some(db) = data_read.get::<StatsDatabaseCache>() {
+        db.lock().await.compilation(&language, "godbolt", response.code != 0, author.id.0, msg.guild_id.map(|g| g.0));
+    }
+
+    // share our exact request as a godbolt.org link, this is a nice-to-have so failures are only logged
+    let state = ClientState {
+        sessions: vec![ClientStateSession {
+            id: 1,
+            language,
+            source: result.code.clone(),
+            compilers: vec![ClientStateCompiler {
+                id: c.id.clone(),
+                options: options.clone(),
+                libs: result.libraries.clone(),
+                filters: Some(ClientStateFilters::from_filters(&filters)),
+            }],
+            executors: Vec::new(),
+        }],
+    };
+    let link = match create_short_link(&state).await {
+        Ok(url) => Some(url),
+        Err(e) => {
+            warn!("Unable to create godbolt short link: {}", e);
+            None
+        }
+    };
+
+    let mut emb = embeds::build_asm_embed(&author, &response, link.as_deref());
+    if result.guessed_language {
+        embeds::add_language_guess(&mut emb, &result.target);
+    }
+    Ok(emb)
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
 pub mod dbl;
+pub mod botlists;
 pub mod wandbox;
 pub mod godbolt;
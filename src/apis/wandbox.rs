@@ -4,36 +4,54 @@ use serenity::framework::standard::CommandError;
 use serenity::builder::{CreateEmbed};
 use serenity::client::Context;
 use serenity::model::channel::Message;
-use crate::cache::{WandboxCache, ConfigCache, StatsManagerCache};
-use std::env;
+use crate::cache::{WandboxCache, ConfigCache, StatsManagerCache, AliasCache, MetricsCache, StatsDatabaseCache, VoteCache};
+use std::time::Instant;
 use serenity::model::user::User;
 use crate::utls::discordhelpers::embeds;
+use crate::cppeval::quickeval::QuickEval;
+use crate::utls::constants::MAX_OUTPUT_LEN;
+use crate::utls::concurrency::Backend;
 
 pub async fn send_request(ctx : Context, mut content : String, author : User, msg : &Message) -> Result<CreateEmbed, CommandError> {
     let data_read = ctx.data.read().await;
     let loading_id;
     let loading_name;
+    let mut max_attachment_size;
+    let url_allow_list;
     {
-        let botinfo_lock = data_read.get::<ConfigCache>().unwrap();
-        let botinfo = botinfo_lock.read().await;
-        loading_id = botinfo
-            .get("LOADING_EMOJI_ID")
-            .unwrap()
-            .clone()
-            .parse::<u64>()
-            .unwrap();
-        loading_name = botinfo.get("LOADING_EMOJI_NAME").unwrap().clone();
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        loading_id = config.emoji.loading_id;
+        loading_name = config.emoji.loading_name.clone();
+        max_attachment_size = config.limits.attachment_bytes();
+        url_allow_list = config.limits.url_allow_list.clone();
     }
 
-    // Try to load in an attachment
-    let attached = parser::get_message_attachment(&msg).await?;
-    if !attached.is_empty() {
-        content.push_str(&format!("\n```\n{}\n```\n", attached));
+    // recent voters can get larger attachments and longer output
+    let max_output;
+    {
+        let votes = data_read.get::<VoteCache>().unwrap().read().await;
+        max_attachment_size = votes.attachment_limit(author.id.0, max_attachment_size);
+        max_output = votes.output_limit(author.id.0, MAX_OUTPUT_LEN);
+    }
+
+    // Try to load in our attachments
+    let attachments = parser::get_message_attachments(&msg, max_attachment_size).await?;
+    if !attachments.files.is_empty() {
+        content.push_str(&format!("\n```\n{}\n```\n", attachments.code()));
     }
 
     // parse user input
     let wandbox_lock = data_read.get::<WandboxCache>().unwrap();
-    let parse_result = parser::get_components(&content, &author, wandbox_lock, &msg.referenced_message).await?;
+    let guild_id = msg.guild_id.map(|g| g.0);
+    let mut parse_result = {
+        let aliases = data_read.get::<AliasCache>().unwrap().read().await;
+        parser::get_components(&content, &author, wandbox_lock, &msg.referenced_message, max_attachment_size, &attachments.filenames(), &aliases, guild_id, &url_allow_list).await?
+    };
+
+    // an attached input.txt is the program's stdin
+    if parse_result.stdin.is_empty() {
+        parse_result.stdin = attachments.stdin;
+    }
 
     // build user input
     let mut builder = CompilationBuilder::new();
@@ -55,6 +73,9 @@ pub async fn send_request(ctx : Context, mut content : String, author : User, ms
         builder.code(&parse_result.code.replacen("public class", "class", 1));
     }
 
+    // wait for a free slot, our ticket holds it until we're done
+    let _ticket = discordhelpers::wait_in_queue(&ctx, msg, &author, Backend::Wandbox).await?;
+
     // send out loading emote
     let reaction = match msg
         .react(&ctx.http, discordhelpers::build_reaction(loading_id, &loading_name))
@@ -67,6 +88,7 @@ pub async fn send_request(ctx : Context, mut content : String, author : User, ms
     };
 
     // dispatch our req
+    let started = Instant::now();
     let mut result = match builder.dispatch().await {
         Ok(r) => r,
         Err(e) => {
@@ -86,29 +108,142 @@ pub async fn send_request(ctx : Context, mut content : String, author : User, ms
             ));
     }
 
+    let metrics = data_read.get::<MetricsCache>().unwrap();
+    metrics.compilation("wandbox", &builder.lang, result.status == "1", started.elapsed());
+    if let Some(db) = data_read.get::<StatsDatabaseCache>() {
+        db.lock().await.compilation(&builder.lang, "wandbox", result.status == "1", author.id.0, msg.guild_id.map(|g| g.0));
+    }
+
     let stats = data_read.get::<StatsManagerCache>().unwrap().lock().await;
     if stats.should_track() {
-        stats.compilation(&builder.lang, result.status == "1").await;
+        stats.compilation(&builder.lang, result.status == "1");
     }
 
     let mut guild = String::from("<unknown>");
     if let Some(g) = msg.guild_id {
         guild = g.to_string()
     }
-    if let Ok(log) = env::var("COMPILE_LOG") {
-        if let Ok(id) = log.parse::<u64>() {
-            let emb = embeds::build_complog_embed(
-                result.status == "1",
-                &parse_result.code,
-                &builder.lang,
-                &author.tag(),
-                author.id.0,
-                &guild,
-            );
-            discordhelpers::manual_dispatch(ctx.http.clone(), id, emb).await;
+    let compile_log = data_read.get::<ConfigCache>().unwrap().read().await.logs.compile;
+    if let Some(id) = compile_log {
+        let emb = embeds::build_complog_embed(
+            result.status == "1",
+            &parse_result.code,
+            &builder.lang,
+            &author.tag(),
+            author.id.0,
+            &guild,
+        );
+        discordhelpers::manual_dispatch(ctx.http.clone(), id, emb).await;
+    }
+
+    let mut emb = embeds::build_compilation_embed(&author, &mut result, max_output);
+    if parse_result.guessed_language {
+        embeds::add_language_guess(&mut emb, &builder.lang);
+    }
+    Ok(emb)
+}
+// Shared by ;cpp, ;c, ;py & ;js - generates a full program from the snippet and runs it
+pub async fn send_quick_eval<T: QuickEval + Send>(ctx : &Context, mut eval : T, author : &User, msg : &Message) -> Result<CreateEmbed, CommandError> {
+    let data_read = ctx.data.read().await;
+    let loading_id;
+    let loading_name;
+    {
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        loading_id = config.emoji.loading_id;
+        loading_name = config.emoji.loading_name.clone();
+    }
+
+    let output = match eval.evaluate() {
+        Ok(o) => o,
+        Err(e) => return Err(CommandError::from(format!("{}", e)))
+    };
+
+    let wandbox_lock = data_read.get::<WandboxCache>().unwrap();
+    let wbox = wandbox_lock.read().await;
+    let compiler = match eval.resolve_compiler(&wbox) {
+        Ok(c) => c,
+        Err(e) => return Err(CommandError::from(format!("{}", e)))
+    };
+    let args = eval.compiler_args();
+    let compiler_info = format!("{} {}", compiler, args.join(" "));
+
+    // --dry only wants to see what we would have compiled
+    if eval.dry_run() {
+        let source = eval.shown_source().unwrap_or(output);
+        return Ok(embeds::build_source_embed(author, &source, eval.highlight(), compiler_info.trim_end()));
+    }
+
+    let mut builder = CompilationBuilder::new();
+    builder.code(&output);
+    builder.target(&compiler);
+    builder.stdin("");
+    builder.save(false);
+    builder.options(args.clone());
+
+    // build request
+    if let Err(e) = builder.build(&wbox) {
+        return Err(CommandError::from(format!(
+            "An internal error has occurred while building request.\n{}",
+            e
+        )));
+    }
+
+    // we're done with the compiler list, no need to hold up a refresh while we wait in line
+    drop(wbox);
+
+    // wait for a free slot, our ticket holds it until we're done
+    let _ticket = discordhelpers::wait_in_queue(ctx, msg, author, Backend::Wandbox).await?;
+
+    // send out loading emote
+    let reaction = match msg
+        .react(&ctx.http, discordhelpers::build_reaction(loading_id, &loading_name))
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(CommandError::from(format!(" Unable to react to message, am I missing permissions to react or use external emoji?\n{}", e)));
+        }
+    };
+
+    // dispatch our req
+    let started = Instant::now();
+    let mut result = match builder.dispatch().await {
+        Ok(r) => r,
+        Err(e) => {
+            // we failed, lets remove the loading react so it doesn't seem like we're still processing
+            msg.delete_reaction_emoji(&ctx.http, reaction.emoji.clone()).await?;
+
+            return Err(CommandError::from(format!("{}", e)));
         }
+    };
+
+    // remove our loading emote
+    if msg.delete_reaction_emoji(&ctx.http, reaction.emoji.clone()).await
+        .is_err()
+    {
+        return Err(CommandError::from(
+                "Unable to remove reactions!\nAm I missing permission to manage messages?",
+            ));
     }
 
-    let emb = embeds::build_compilation_embed(&author, &mut result);
+    let metrics = data_read.get::<MetricsCache>().unwrap();
+    metrics.compilation("wandbox", &builder.lang, result.status == "1", started.elapsed());
+    if let Some(db) = data_read.get::<StatsDatabaseCache>() {
+        db.lock().await.compilation(&builder.lang, "wandbox", result.status == "1", author.id.0, msg.guild_id.map(|g| g.0));
+    }
+
+    let stats = data_read.get::<StatsManagerCache>().unwrap().lock().await;
+    if stats.should_track() {
+        stats.compilation(&builder.lang, result.status == "1");
+    }
+
+    // our prelude shifts line numbers around, point diagnostics back at the user's snippet
+    result.compiler_all = eval.translate_diagnostics(&result.compiler_all);
+
+    let max_output = data_read.get::<VoteCache>().unwrap().read().await.output_limit(author.id.0, MAX_OUTPUT_LEN);
+    let mut emb = embeds::build_small_compilation_embed(author, &mut result, compiler_info.trim_end(), max_output);
+    if let Some(source) = eval.shown_source() {
+        embeds::add_generated_source(&mut emb, &source, eval.highlight());
+    }
     Ok(emb)
-}
\ No newline at end of file
+}
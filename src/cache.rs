@@ -1,18 +1,33 @@
-use std::collections::HashMap;
-use std::env;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::error::Error;
+use std::time::Duration;
 
 use tokio::sync::RwLock;
 use tokio::sync::Mutex;
 
 use serenity::prelude::{TypeMap, TypeMapKey};
-use serenity::model::id::UserId;
 use serenity::client::bridge::gateway::ShardManager;
 
+use crate::config::{self, Config};
 use crate::stats::statsmanager::StatsManager;
+use crate::stats::metrics::Metrics;
+use crate::stats::database::StatsDatabase;
 use crate::utls::blocklist::Blocklist;
+use crate::utls::aliases::AliasTable;
+use crate::utls::votes::{VoteTracker, VotePerks};
+use crate::utls::announcements::Announcements;
+use crate::utls::maintenance::Maintenance;
+use crate::utls::ratelimit::RateLimiter;
+use crate::utls::shutdown::Shutdown;
+use crate::utls::concurrency::RequestQueue;
+use crate::utls::discordhelpers::EditHandler;
+use crate::commands;
+use crate::apis::botlists;
+use crate::apis::botlists::BotListProvider;
 
+use notify::{EventKind, RecursiveMode, Watcher};
 use godbolt::Godbolt;
 use wandbox::Wandbox;
 use lru_cache::LruCache;
@@ -20,10 +35,10 @@ use serenity::model::channel::Message;
 
 /** Caching **/
 
-/// Contains bot configuration information provided mostly from environment variables
+/// Our configuration, from config.toml and the environment - see config.rs
 pub struct ConfigCache;
 impl TypeMapKey for ConfigCache {
-    type Value = Arc<RwLock<HashMap<&'static str, String>>>;
+    type Value = Arc<RwLock<Config>>;
 }
 
 /// The cache of all compilers/languages from wandbox - along with our bindings for their api
@@ -38,10 +53,10 @@ impl TypeMapKey for GodboltCache {
     type Value = Arc<RwLock<Godbolt>>;
 }
 
-/// Contains our top.gg api client for server count updates
-pub struct DblCache;
-impl TypeMapKey for DblCache {
-    type Value = Arc<RwLock<dbl::Client>>;
+/// Bot list sites we post server counts to and accept votes from
+pub struct BotListCache;
+impl TypeMapKey for BotListCache {
+    type Value = Arc<Vec<Box<dyn BotListProvider>>>;
 }
 
 /// Our endpoints for the in-house statistics tracing - see apis/dbl.rs
@@ -56,75 +71,280 @@ impl TypeMapKey for BlocklistCache {
     type Value = Arc<RwLock<Blocklist>>;
 }
 
+/// Language aliases (cpp -> c++, c++20 -> c++ -std=c++20, etc) and their per-guild overrides
+pub struct AliasCache;
+impl TypeMapKey for AliasCache {
+    type Value = Arc<RwLock<AliasTable>>;
+}
+
+/// Everyone who's voted for us, and the perks they get for it
+pub struct VoteCache;
+impl TypeMapKey for VoteCache {
+    type Value = Arc<RwLock<VoteTracker>>;
+}
+
+/// Channels guilds have opted in to receive our announcements in
+pub struct AnnouncementCache;
+impl TypeMapKey for AnnouncementCache {
+    type Value = Arc<RwLock<Announcements>>;
+}
+
+/// Whether we're turning away commands for maintenance
+pub struct MaintenanceCache;
+impl TypeMapKey for MaintenanceCache {
+    type Value = Arc<RwLock<Maintenance>>;
+}
+
+/// Recent uses of our rate limited commands, see config.ratelimit
+pub struct RateLimitCache;
+impl TypeMapKey for RateLimitCache {
+    type Value = Arc<Mutex<RateLimiter>>;
+}
+
+/// Compilations running and waiting for a slot, see config.concurrency
+pub struct RequestQueueCache;
+impl TypeMapKey for RequestQueueCache {
+    type Value = Arc<RequestQueue>;
+}
+
+/// Whether we're shutting down, and the commands we're waiting on
+pub struct ShutdownCache;
+impl TypeMapKey for ShutdownCache {
+    type Value = Arc<Shutdown>;
+}
+
 /// Contains the shard manager - used to send global presence updates
 pub struct ShardManagerCache;
 impl TypeMapKey for ShardManagerCache {
     type Value = Arc<Mutex<ShardManager>>;
 }
 
+/// Prometheus metrics, exposed on /metrics by our webhook server
+pub struct MetricsCache;
+impl TypeMapKey for MetricsCache {
+    type Value = Arc<Metrics>;
+}
+
+/// Local statistics database, only present if STATS_DATABASE is set
+pub struct StatsDatabaseCache;
+impl TypeMapKey for StatsDatabaseCache {
+    type Value = Arc<Mutex<StatsDatabase>>;
+}
+
+/// One of our replies, along with the command that produced it
+#[derive(Clone)]
+pub struct CachedMessage {
+    pub command: String,
+    pub message: Message,
+}
+
 /// Message  cache to interact with our own messages after they are dispatched
 pub struct MessageCache;
 impl TypeMapKey for MessageCache {
-    type Value = Arc<Mutex<LruCache<u64, Message>>>;
+    type Value = Arc<Mutex<LruCache<u64, CachedMessage>>>;
+}
+
+/// Commands that can be re-run by editing the message that invoked them
+pub struct EditHandlerCache;
+impl TypeMapKey for EditHandlerCache {
+    type Value = Arc<HashMap<&'static str, EditHandler>>;
 }
 
 pub async fn fill(
     data: Arc<RwLock<TypeMap>>,
-    prefix: &str,
-    id: &UserId,
+    config: Config,
     shard_manager: Arc<tokio::sync::Mutex<ShardManager>>
 ) -> Result<(), Box<dyn Error>> {
     let mut data = data.write().await;
 
-    // Lets map some common things in BotInfo
-    let mut map = HashMap::<&str, String>::new();
-    map.insert("SUCCESS_EMOJI_ID", env::var("SUCCESS_EMOJI_ID")?);
-    map.insert("SUCCESS_EMOJI_NAME", env::var("SUCCESS_EMOJI_NAME")?);
-    map.insert("LOADING_EMOJI_ID", env::var("LOADING_EMOJI_ID")?);
-    map.insert("LOADING_EMOJI_NAME", env::var("LOADING_EMOJI_NAME")?);
-    map.insert("GIT_HASH_LONG", String::from(env!("GIT_HASH_LONG")));
-    map.insert("GIT_HASH_SHORT", String::from(env!("GIT_HASH_SHORT")));
-    map.insert("JOIN_LOG", env::var("JOIN_LOG")?);
-    map.insert("BOT_PREFIX", String::from(prefix));
-    map.insert("BOT_ID", id.to_string());
-    data.insert::<ConfigCache>(Arc::new(RwLock::new(map)));
-
     // Shard manager for universal presence
     data.insert::<ShardManagerCache>(shard_manager);
 
     // Wandbox
-    let mut broken_compilers = std::collections::HashSet::new();
-    broken_compilers.insert(String::from("ghc-head"));
-    broken_compilers.insert(String::from("go-head"));
-    let mut broken_languages = std::collections::HashSet::new();
-    broken_languages.insert(String::from("cpp"));
-    let wbox = wandbox::Wandbox::new(Some(broken_compilers), Some(broken_languages)).await?;
+    let wbox = load_wandbox().await?;
     info!("WandBox cache loaded");
     data.insert::<WandboxCache>(Arc::new(RwLock::new(wbox)));
 
     // Message delete cache
     data.insert::<MessageCache>(Arc::new(tokio::sync::Mutex::new(LruCache::new(25))));
 
+    // Edit handlers, keyed by the command's name
+    let mut edit_handlers = HashMap::<&'static str, EditHandler>::new();
+    edit_handlers.insert("asm", commands::asm::asm_edit);
+    edit_handlers.insert("compile", commands::compile::compile_edit);
+    edit_handlers.insert("cpp", commands::cpp::cpp_edit);
+    edit_handlers.insert("c", commands::c::c_edit);
+    edit_handlers.insert("py", commands::py::py_edit);
+    edit_handlers.insert("js", commands::js::js_edit);
+    data.insert::<EditHandlerCache>(Arc::new(edit_handlers));
+
     // Godbolt
     let godbolt = Godbolt::new().await?;
     info!("Godbolt cache loaded");
     data.insert::<GodboltCache>(Arc::new(RwLock::new(godbolt)));
 
-    // DBL
-    let token = env::var("DBL_TOKEN")?;
-    let client = dbl::Client::new(token)?;
-    data.insert::<DblCache>(Arc::new(RwLock::new(client)));
+    // Bot lists
+    let providers = botlists::providers_from_config(&config.botlists);
+    info!(
+        "Bot lists enabled: {}",
+        providers.iter().map(|p| p.name()).collect::<Vec<&str>>().join(", ")
+    );
+    data.insert::<BotListCache>(Arc::new(providers));
 
     // Stats tracking
-    let stats = StatsManager::new();
+    let stats = StatsManager::new(&config.stats);
     if stats.should_track() {
         info!("Statistics tracking enabled");
     }
     data.insert::<StatsManagerCache>(Arc::new(Mutex::new(stats)));
 
+    // Local metrics, these are always collected but only served if webhook.metrics is set
+    data.insert::<MetricsCache>(Arc::new(Metrics::new()));
+
+    // Local statistics database
+    if let Some(path) = &config.stats.database {
+        match StatsDatabase::open(path) {
+            Ok(db) => {
+                info!("Statistics database loaded from {}", path);
+                data.insert::<StatsDatabaseCache>(Arc::new(Mutex::new(db)));
+            }
+            Err(e) => warn!("Unable to open statistics database {}: {}", path, e),
+        }
+    }
+
     // Blocklist
-    let blocklist = Blocklist::new();
+    let blocklist = Blocklist::load()?;
     data.insert::<BlocklistCache>(Arc::new(RwLock::new(blocklist)));
 
+    // Language aliases
+    let aliases = AliasTable::load()?;
+    info!("Loaded {} language aliases", aliases.count());
+    data.insert::<AliasCache>(Arc::new(RwLock::new(aliases)));
+
+    // Votes
+    let votes = VoteTracker::new(VotePerks::from(&config.votes));
+    data.insert::<VoteCache>(Arc::new(RwLock::new(votes)));
+
+    // Announcement channels
+    let announcements = Announcements::new();
+    data.insert::<AnnouncementCache>(Arc::new(RwLock::new(announcements)));
+
+    // Maintenance mode, always starts off
+    data.insert::<MaintenanceCache>(Arc::new(RwLock::new(Maintenance::default())));
+
+    // Nospam limits
+    data.insert::<RateLimitCache>(Arc::new(Mutex::new(RateLimiter::default())));
+
+    data.insert::<ShutdownCache>(Arc::new(Shutdown::default()));
+
+    // Concurrency limits
+    data.insert::<RequestQueueCache>(Arc::new(RequestQueue::new(config.concurrency.clone())));
+
+    data.insert::<ConfigCache>(Arc::new(RwLock::new(config)));
+    Ok(())
+}
+
+async fn load_wandbox() -> Result<Wandbox, Box<dyn Error>> {
+    let mut broken_compilers = std::collections::HashSet::new();
+    broken_compilers.insert(String::from("ghc-head"));
+    broken_compilers.insert(String::from("go-head"));
+    let mut broken_languages = std::collections::HashSet::new();
+    broken_languages.insert(String::from("cpp"));
+    Ok(wandbox::Wandbox::new(Some(broken_compilers), Some(broken_languages)).await?)
+}
+
+// Re-reads our .env file, our config and everything loaded from disk. Returns the changed settings
+// that are only read at startup, those keep their current values until we restart.
+pub async fn reload(data: Arc<RwLock<TypeMap>>) -> Result<Vec<&'static str>, Box<dyn Error>> {
+    let data = data.read().await;
+    {
+        let mut current = data.get::<ConfigCache>().unwrap().write().await;
+        current.runtime.env_keys = config::apply_dotenv(&current.runtime.env_keys);
+    }
+
+    // everything is loaded before anything is replaced, a mistake in one file leaves us as we were
+    let mut config = Config::load()?;
+    let blocklist = Blocklist::load()?;
+    let aliases = AliasTable::load()?;
+
+    *data.get::<BlocklistCache>().unwrap().write().await = blocklist;
+    *data.get::<AliasCache>().unwrap().write().await = aliases;
+    data.get::<VoteCache>().unwrap().write().await.perks = VotePerks::from(&config.votes);
+    data.get::<RequestQueueCache>().unwrap().set_limits(config.concurrency.clone());
+
+    let mut current = data.get::<ConfigCache>().unwrap().write().await;
+    let restart_required = current.restart_required(&config);
+    config.bot = std::mem::take(&mut current.bot);
+    config.logs.vote = current.logs.vote;
+    config.webhook = std::mem::take(&mut current.webhook);
+    config.botlists = std::mem::take(&mut current.botlists);
+    config.stats = std::mem::take(&mut current.stats);
+    config.runtime = std::mem::take(&mut current.runtime);
+    *current = config;
+    Ok(restart_required)
+}
+
+// Reloads whenever our config file or .env changes on disk
+pub fn spawn_config_watcher(data: Arc<RwLock<TypeMap>>) {
+    let files = vec![PathBuf::from(Config::path()), PathBuf::from(".env")];
+    let names: Vec<_> = files.iter().filter_map(|f| f.file_name().map(|n| n.to_os_string())).collect();
+
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let changed = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event.paths.iter().any(|p| p.file_name().map_or(false, |n| names.iter().any(|name| name == n)));
+            if changed {
+                let _ = sender.send(());
+            }
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(w) => w,
+        Err(e) => {
+            warn!("Unable to watch configuration for changes: {}", e);
+            return;
+        }
+    };
+
+    // editors tend to replace files instead of writing to them, so we watch their directories
+    let dirs: HashSet<PathBuf> = files
+        .iter()
+        .map(|f| match f.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        })
+        .collect();
+    for dir in &dirs {
+        if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            warn!("Unable to watch {} for configuration changes: {}", dir.display(), e);
+        }
+    }
+
+    tokio::spawn(async move {
+        // the watcher stops once it's dropped
+        let _watcher = watcher;
+        while receiver.recv().await.is_some() {
+            // a single save usually shows up as a burst of events
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            while receiver.try_recv().is_ok() {}
+
+            match reload(data.clone()).await {
+                Ok(restart_required) if restart_required.is_empty() => info!("Configuration reloaded"),
+                Ok(restart_required) => warn!("Configuration reloaded, changes to {} need a restart", restart_required.join(", ")),
+                Err(e) => warn!("Unable to reload configuration: {}", e),
+            }
+        }
+    });
+}
+
+// Fetches fresh compiler lists from wandbox & godbolt
+pub async fn refresh_compilers(data: Arc<RwLock<TypeMap>>) -> Result<(), Box<dyn Error>> {
+    let wbox = load_wandbox().await?;
+    let godbolt = Godbolt::new().await?;
+
+    let data = data.read().await;
+    *data.get::<WandboxCache>().unwrap().write().await = wbox;
+    *data.get::<GodboltCache>().unwrap().write().await = godbolt;
     Ok(())
 }
@@ -0,0 +1,193 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult, CommandError};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::cache::*;
+use crate::utls::discordhelpers;
+use crate::utls::discordhelpers::embeds;
+use crate::utls::maintenance::MaintenanceScope;
+
+#[command]
+#[owners_only]
+#[sub_commands(reload, refresh, status, broadcast, leave, maintenance)]
+pub async fn admin(ctx: &Context, msg: &Message, _: Args) -> CommandResult {
+    msg.channel_id.say(&ctx.http,
+        "Available subcommands: `reload`, `refresh`, `status`, `broadcast <message>`, `leave <guild id>`, `maintenance <on|off> [global|wandbox|godbolt] [notice]`"
+    ).await?;
+    Ok(())
+}
+
+// the framework hands hooks the name of the subcommand that ran, not ours
+pub fn is_admin_command(name : &str) -> bool {
+    ADMIN_COMMAND.options.names.contains(&name)
+        || ADMIN_COMMAND.options.sub_commands.iter().any(|c| c.options.names.contains(&name))
+}
+
+#[command]
+#[owners_only]
+async fn reload(ctx: &Context, msg: &Message, _: Args) -> CommandResult {
+    let restart_required = match crate::cache::reload(ctx.data.clone()).await {
+        Ok(r) => r,
+        Err(e) => return Err(CommandError::from(format!("Unable to reload configuration\n{}", e)))
+    };
+
+    discordhelpers::audit_log(ctx, &msg.author, "Reloaded configuration").await;
+    if restart_required.is_empty() {
+        msg.channel_id.say(&ctx.http, "Configuration reloaded").await?;
+    }
+    else {
+        msg.channel_id.say(&ctx.http, format!("Configuration reloaded, changes to `{}` need a restart", restart_required.join("`, `"))).await?;
+    }
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+async fn refresh(ctx: &Context, msg: &Message, _: Args) -> CommandResult {
+    if let Err(e) = crate::cache::refresh_compilers(ctx.data.clone()).await {
+        return Err(CommandError::from(format!("Unable to refresh compiler caches\n{}", e)));
+    }
+
+    discordhelpers::audit_log(ctx, &msg.author, "Refreshed compiler caches").await;
+    msg.channel_id.say(&ctx.http, "Compiler caches refreshed").await?;
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+async fn status(ctx: &Context, msg: &Message, _: Args) -> CommandResult {
+    let status = {
+        let data = ctx.data.read().await;
+
+        let shards = {
+            let shard_manager = data.get::<ShardManagerCache>().unwrap().lock().await;
+            let runners = shard_manager.runners.lock().await;
+            let mut shards: Vec<(u64, Option<std::time::Duration>, String)> = runners
+                .iter()
+                .map(|(id, info)| (id.0, info.latency, format!("{:?}", info.stage)))
+                .collect();
+            shards.sort_by_key(|s| s.0);
+            shards
+        };
+
+        let caches = vec![
+            ("messages", data.get::<MessageCache>().unwrap().lock().await.len()),
+            ("godbolt languages", data.get::<GodboltCache>().unwrap().read().await.cache.len()),
+            ("aliases", data.get::<AliasCache>().unwrap().read().await.count()),
+            ("announcement channels", data.get::<AnnouncementCache>().unwrap().read().await.channels().len()),
+        ];
+
+        let stats = data.get::<StatsManagerCache>().unwrap().lock().await;
+        embeds::StatusInfo {
+            uptime: stats.uptime(),
+            guilds: stats.server_count(),
+            shards,
+            caches,
+            stats_queue: stats.queue_depth(),
+            requests: data.get::<RequestQueueCache>().unwrap().depth(),
+            maintenance: data.get::<MaintenanceCache>().unwrap().read().await.status(),
+        }
+    };
+
+    let emb = embeds::build_status_embed(&msg.author, &status);
+    let mut emb_msg = embeds::embed_message(emb);
+    msg.channel_id.send_message(&ctx.http, |_| &mut emb_msg).await?;
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+async fn broadcast(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let announcement = args.rest();
+    if announcement.is_empty() {
+        return Err(CommandError::from("Supply a message to broadcast"));
+    }
+
+    let channels = {
+        let data = ctx.data.read().await;
+        let announcements = data.get::<AnnouncementCache>().unwrap().read().await;
+        announcements.channels()
+    };
+
+    let mut failed = 0;
+    for channel in &channels {
+        let emb = embeds::build_announcement_embed(announcement);
+        let mut emb_msg = embeds::embed_message(emb);
+        if let Err(e) = ChannelId(*channel).send_message(&ctx.http, |_| &mut emb_msg).await {
+            warn!("Unable to send announcement to {}: {}", channel, e);
+            failed += 1;
+        }
+    }
+
+    discordhelpers::audit_log(ctx, &msg.author, &format!("Broadcast to {} channels:\n{}", channels.len() - failed, announcement)).await;
+    msg.channel_id.say(&ctx.http, format!("Sent to {} channels, {} failed", channels.len() - failed, failed)).await?;
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+async fn leave(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    if args.len() != 1 {
+        return Err(CommandError::from("Supply a guild id to leave"));
+    }
+
+    let guild = args.parse::<u64>()?;
+    GuildId(guild).leave(&ctx.http).await?;
+
+    discordhelpers::audit_log(ctx, &msg.author, &format!("Left guild `{}`", guild)).await;
+    msg.channel_id.say(&ctx.http, format!("Left guild `{}`", guild)).await?;
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+async fn maintenance(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let usage = "Usage: maintenance <on|off> [global|wandbox|godbolt] [notice]";
+    let toggle = args.single::<String>().unwrap_or_default();
+
+    // the scope is optional, anything else is the start of our notice
+    let mut scope = MaintenanceScope::Global;
+    if let Ok(arg) = args.parse::<String>() {
+        if let Some(s) = MaintenanceScope::parse(&arg) {
+            scope = s;
+            args.advance();
+        }
+    }
+    let notice = args.rest();
+
+    let action = {
+        let data = ctx.data.read().await;
+        let action = {
+            let mut maintenance = data.get::<MaintenanceCache>().unwrap().write().await;
+            match toggle.as_str() {
+                "on" => {
+                    maintenance.enable(scope, if notice.is_empty() { None } else { Some(String::from(notice)) });
+                    format!("Enabled {} maintenance", scope.name())
+                }
+                "off" if scope == MaintenanceScope::Global => {
+                    // turning off global maintenance clears everything
+                    maintenance.disable_all();
+                    String::from("Disabled all maintenance")
+                }
+                "off" => {
+                    if !maintenance.disable(scope) {
+                        return Err(CommandError::from(format!("{} isn't under maintenance", scope.name())));
+                    }
+                    format!("Disabled {} maintenance", scope.name())
+                }
+                _ => return Err(CommandError::from(usage))
+            }
+        };
+
+        // our presence shows what's under maintenance
+        let stats = data.get::<StatsManagerCache>().unwrap().lock().await;
+        let status = data.get::<MaintenanceCache>().unwrap().read().await.status();
+        let shard_manager = data.get::<ShardManagerCache>().unwrap().lock().await;
+        discordhelpers::send_global_presence(&shard_manager, stats.server_count(), status).await;
+        action
+    };
+
+    discordhelpers::audit_log(ctx, &msg.author, &action).await;
+    msg.channel_id.say(&ctx.http, action).await?;
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult, CommandError};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::cache::AnnouncementCache;
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions("MANAGE_GUILD")]
+pub async fn announcements(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild = match msg.guild_id {
+        Some(g) => g.0,
+        None => return Err(CommandError::from("This command can only be used in a server")),
+    };
+
+    let reply = {
+        let data = ctx.data.read().await;
+        let mut announcements = data.get::<AnnouncementCache>().unwrap().write().await;
+        if args.message() == "off" {
+            if announcements.unsubscribe(guild) {
+                "This server will no longer receive announcements"
+            } else {
+                "This server isn't receiving announcements"
+            }
+        } else {
+            announcements.subscribe(guild, msg.channel_id.0);
+            "Announcements will be posted in this channel"
+        }
+    };
+
+    msg.channel_id.say(&ctx.http, reply).await?;
+    Ok(())
+}
@@ -5,14 +5,13 @@ use serenity::{
 };
 use serenity_utils::menu::Menu;
 
-use crate::cache::{GodboltCache, ConfigCache, MessageCache};
+use crate::cache::{GodboltCache, ConfigCache, MessageCache, CachedMessage};
 use crate::utls::constants::*;
 use crate::utls::{discordhelpers};
-use crate::utls::discordhelpers::embeds;
+use crate::utls::discordhelpers::{embeds, EditFuture};
 
 #[command]
 #[sub_commands(compilers, languages)]
-#[bucket = "nospam"]
 pub async fn asm(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
     let emb = crate::apis::godbolt::send_request(ctx.clone(), msg.content.clone(), msg.author.clone(), msg).await?;
     let mut emb_msg = embeds::embed_message(emb);
@@ -27,11 +26,15 @@ pub async fn asm(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
 
     let data_read = ctx.data.read().await;
     let mut message_cache = data_read.get::<MessageCache>().unwrap().lock().await;
-    message_cache.insert(msg.id.0, asm_embed.clone());
+    message_cache.insert(msg.id.0, CachedMessage { command: String::from("asm"), message: asm_embed.clone() });
     debug!("Command executed");
     Ok(())
 }
 
+pub fn asm_edit(ctx : &Context, content : String, author : User, old : Message) -> EditFuture<'_> {
+    Box::pin(discordhelpers::handle_edit_asm(ctx, content, author, old))
+}
+
 #[command]
 async fn compilers(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let data_read = ctx.data.read().await;
@@ -66,18 +69,9 @@ async fn compilers(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let success_name;
     {
         let data_read = ctx.data.read().await;
-        let botinfo_lock = data_read
-            .get::<ConfigCache>()
-            .expect("Expected BotInfo in global cache")
-            .clone();
-        let botinfo = botinfo_lock.read().await;
-        success_id = botinfo
-            .get("SUCCESS_EMOJI_ID")
-            .unwrap()
-            .clone()
-            .parse::<u64>()
-            .unwrap();
-        success_name = botinfo.get("SUCCESS_EMOJI_NAME").unwrap().clone();
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        success_id = config.emoji.success_id;
+        success_name = config.emoji.success_name.clone();
     }
 
     let options = discordhelpers::build_menu_controls();
@@ -146,18 +140,9 @@ async fn languages(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
     let success_name;
     {
         let data_read = ctx.data.read().await;
-        let botinfo_lock = data_read
-            .get::<ConfigCache>()
-            .expect("Expected BotInfo in global cache")
-            .clone();
-        let botinfo = botinfo_lock.read().await;
-        success_id = botinfo
-            .get("SUCCESS_EMOJI_ID")
-            .unwrap()
-            .clone()
-            .parse::<u64>()
-            .unwrap();
-        success_name = botinfo.get("SUCCESS_EMOJI_NAME").unwrap().clone();
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        success_id = config.emoji.success_id;
+        success_name = config.emoji.success_name.clone();
     }
 
     let options = discordhelpers::build_menu_controls();
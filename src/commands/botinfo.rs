@@ -3,30 +3,26 @@ use serenity::framework::standard::{macros::command, Args, CommandResult};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 
-use std::env;
-
 use crate::cache::ConfigCache;
 use crate::utls::constants::COLOR_OKAY;
 
 #[command]
 pub async fn botinfo(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
-    let invite = env::var("INVITE_LINK").expect("Expected invite link envvar");
-    let topgg = env::var("DISCORDBOTS_LINK").expect("Expected top.gg link envvar");
-    let github = env::var("GITHUB_LINK").expect("Expected github link envvar");
-    let stats = env::var("STATS_LINK").expect("Expected stats link envvar");
+    let hash_short = env!("GIT_HASH_SHORT");
+    let hash_long = env!("GIT_HASH_LONG");
 
-    let hash_short;
-    let hash_long;
+    let invite;
+    let topgg;
+    let github;
+    let stats;
     let avatar = {
         let data_read = ctx.data.read().await;
-        let botinfo_lock = data_read
-            .get::<ConfigCache>()
-            .expect("Expected ConfigCache in global cache")
-            .clone();
-        let botinfo = botinfo_lock.read().await;
-        hash_short = botinfo.get("GIT_HASH_SHORT").unwrap().clone();
-        hash_long = botinfo.get("GIT_HASH_LONG").unwrap().clone();
-        botinfo.get("BOT_AVATAR").unwrap().clone()
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        invite = config.links.invite.clone();
+        topgg = config.links.discordbots.clone();
+        github = config.links.github.clone();
+        stats = config.links.stats.clone();
+        config.runtime.avatar.clone()
     };
 
     let msg = msg
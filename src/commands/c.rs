@@ -0,0 +1,16 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::cppeval::ceval::CEval;
+use crate::utls::discordhelpers;
+use crate::utls::discordhelpers::EditFuture;
+
+#[command]
+pub async fn c(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    discordhelpers::run_quick_eval(ctx, msg, CEval::new, "c").await
+}
+
+pub fn c_edit(ctx : &Context, content : String, author : User, old : Message) -> EditFuture<'_> {
+    Box::pin(discordhelpers::handle_edit_quick_eval(ctx, content, author, old, CEval::new, "c"))
+}
@@ -2,13 +2,12 @@ use serenity::framework::standard::{macros::command, Args, CommandResult};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 
-use crate::cache::{MessageCache};
+use crate::cache::{MessageCache, CachedMessage};
 use crate::utls::{discordhelpers};
 use crate::utls::constants::COLOR_OKAY;
-use crate::utls::discordhelpers::embeds;
+use crate::utls::discordhelpers::{embeds, EditFuture};
 
 #[command]
-#[bucket = "nospam"]
 pub async fn compile(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
     let data_read = ctx.data.read().await;
 
@@ -27,7 +26,11 @@ pub async fn compile(ctx: &Context, msg: &Message, _args: Args) -> CommandResult
     discordhelpers::send_completion_react(ctx, &compilation_embed, compilation_successful).await?;
 
     let mut delete_cache = data_read.get::<MessageCache>().unwrap().lock().await;
-    delete_cache.insert(msg.id.0, compilation_embed);
+    delete_cache.insert(msg.id.0, CachedMessage { command: String::from("compile"), message: compilation_embed });
     debug!("Command executed");
     Ok(())
 }
+
+pub fn compile_edit(ctx : &Context, content : String, author : User, old : Message) -> EditFuture<'_> {
+    Box::pin(discordhelpers::handle_edit_compile(ctx, content, author, old))
+}
@@ -1,5 +1,3 @@
-use std::env;
-
 use serenity::{
     builder::CreateEmbed,
     framework::standard::{macros::command, Args, CommandResult},
@@ -7,12 +5,17 @@ use serenity::{
     prelude::*,
 };
 
+use crate::cache::ConfigCache;
 use crate::utls::constants::*;
 use crate::utls::discordhelpers::embeds;
 
 #[command]
 pub async fn help(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let prefix = env::var("BOT_PREFIX").expect("Bot prefix is not set!");
+    let prefix = {
+        let data_read = ctx.data.read().await;
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        config.bot.prefix.clone()
+    };
     if !args.is_empty() {
         let cmd = args.parse::<String>().unwrap();
         let mut emb = CreateEmbed::default();
@@ -58,9 +61,54 @@ pub async fn help(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 emb.field("Example 3", format!("{}cpp << f(2); int f(int a) {{ return a*12; }}", prefix), false);
                 emb.field("Example 4", format!("{}cpp int main() {{ cout << \"Main\"; f(); }} void f() {{ cout << \"f()\"; }}", prefix), false);
                 emb.field("Example 5", format!("*You may also use in-line code blocks if discord makes you escape some chars*\n{}cpp `<< (4*12) << \"\\\"Hello world!\\\"\"`", prefix), false);
+                emb.field("Example 6", format!("*Choose a standard, compiler, or optimization level before your snippet*\n{}cpp -std=c++17 --clang -O0 << sizeof(long)", prefix), false);
+                emb.field("Example 7", format!("*See the program we generate with `--source`, or only show it with `--dry` (add `--prelude` to include our headers)*\n{}cpp --dry << f(2); int f(int a) {{ return a*12; }}", prefix), false);
                 "Allows you to quickly compile and execute c++ snippets using geordi-like syntax.\nSee section 2.1 of http://eel.is/geordi/#syntax"
             }
 
+            "c" => {
+                emb.title("c command");
+                emb.field("Example 1", format!("{}c {{ int a = 4; if (a > 3) {{ puts(\"true\"); }} }}", prefix), false);
+                emb.field("Example 2", format!("{}c << (4*12) << \" \" << 2.5 << \" \" << \"Hello world!\"", prefix), false);
+                emb.field("Example 3", format!("{}c << f(2); int f(int a) {{ return a*12; }}", prefix), false);
+                emb.field("Example 4", format!("{}c int main(void) {{ f(); }} void f(void) {{ puts(\"f()\"); }}", prefix), false);
+                "Allows you to quickly compile and execute c snippets using the same syntax as the cpp command.\nEach value after `<<` is printed with a format chosen from its type, parenthesize any shifts"
+            }
+
+            "py" | "python" => {
+                emb.title("py command");
+                emb.field("Example 1", format!("{}py [x**2 for x in range(5)]", prefix), false);
+                emb.field("Example 2", format!("{}py import math; math.factorial(20)", prefix), false);
+                "Runs a python snippet, printing the value of the last expression like the interactive interpreter would"
+            }
+
+            "js" | "javascript" | "node" => {
+                emb.title("js command");
+                emb.field("Example 1", format!("{}js [1, 2, 3].map(x => x * 2)", prefix), false);
+                emb.field("Example 2", format!("{}js const o = {{ a: 1 }}; Object.keys(o)", prefix), false);
+                "Runs a javascript snippet in node, printing the value of the last expression like the REPL would"
+            }
+
+            "stats" => {
+                emb.title("Stats command");
+                emb.field("Example 1", format!("{}stats week", prefix), false);
+                emb.field("Example 2", format!("{}stats me month", prefix), false);
+                "Shows the most used languages and how busy we've been over the last day, week or month.\nUse `stats me` to see your own usage"
+            }
+
+            "vote" => {
+                emb.title("Vote command");
+                emb.field("Example", format!("{}vote", prefix), false);
+                "Shows where to vote for the bot, when you last voted and your voting streak.\nRecent voters get some perks, like longer program output"
+            }
+
+            "announcements" => {
+                emb.title("Announcements command");
+                emb.field("Example 1", format!("{}announcements", prefix), false);
+                emb.field("Example 2", format!("{}announcements off", prefix), false);
+                "Posts announcements about the bot, like outages or new features, in the current channel.\nRequires the Manage Server permission"
+            }
+
             "languages" => {
                 emb.title("Languages command");
                 emb.field("Example", format!("{}languages", prefix), false);
@@ -107,7 +155,6 @@ pub async fn help(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         return Ok(());
     }
 
-    let prefix = env::var("BOT_PREFIX").expect("Prefix has not been set!");
     msg.channel_id.send_message(&ctx.http, |m| {
         m.embed(|e| {
             e.thumbnail(ICON_HELP);
@@ -121,6 +168,11 @@ pub async fn help(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             e.field("asm", "```\nOutputs the assembly for the input code```", false);
             e.field("botinfo", "``` Displays information about the bot ```", false);
             e.field("cpp", format!("``` Executes c++ code using geordi-like syntax\n See {}help cpp for more info ```", prefix), false);
+            e.field("c", format!("``` Executes c code using geordi-like syntax\n See {}help c for more info ```", prefix), false);
+            e.field("stats", "``` Displays usage statistics ```", false);
+            e.field("vote", "``` Displays your voting streak and perks ```", false);
+            e.field("announcements", "``` Receive bot announcements in this channel ```", false);
+            e.field("py/js", format!("``` Evaluates a python or javascript expression\n See {}help py for more info ```", prefix), false);
             e
         })
     }).await?;
@@ -2,13 +2,16 @@ use serenity::framework::standard::{macros::command, Args, CommandResult};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 
-use std::env;
-
+use crate::cache::ConfigCache;
 use crate::utls::discordhelpers::embeds;
 
 #[command]
 pub async fn invite(ctx: &Context, msg: &Message, _: Args) -> CommandResult {
-    let invite = env::var("INVITE_LINK").expect("Expected invite link envvar");
+    let invite = {
+        let data_read = ctx.data.read().await;
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        config.links.invite.clone()
+    };
 
     let emb = embeds::build_invite_embed(&invite);
 
@@ -0,0 +1,17 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::cppeval::scripteval::JavaScriptEval;
+use crate::utls::discordhelpers;
+use crate::utls::discordhelpers::EditFuture;
+
+#[command]
+#[aliases("javascript", "node")]
+pub async fn js(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    discordhelpers::run_quick_eval(ctx, msg, JavaScriptEval::new, "js").await
+}
+
+pub fn js_edit(ctx : &Context, content : String, author : User, old : Message) -> EditFuture<'_> {
+    Box::pin(discordhelpers::handle_edit_quick_eval(ctx, content, author, old, JavaScriptEval::new, "js"))
+}
@@ -31,19 +31,10 @@ pub async fn languages(ctx: &Context, msg: &Message, _args: Args) -> CommandResu
     let success_name;
     {
         let data_read = ctx.data.read().await;
-        let botinfo_lock = data_read
-            .get::<ConfigCache>()
-            .expect("Expected BotInfo in global cache")
-            .clone();
-        let botinfo = botinfo_lock.read().await;
-        avatar = botinfo.get("BOT_AVATAR").unwrap().clone();
-        success_id = botinfo
-            .get("SUCCESS_EMOJI_ID")
-            .unwrap()
-            .clone()
-            .parse::<u64>()
-            .unwrap();
-        success_name = botinfo.get("SUCCESS_EMOJI_NAME").unwrap().clone();
+        let config = data_read.get::<ConfigCache>().unwrap().read().await;
+        avatar = config.runtime.avatar.clone();
+        success_id = config.emoji.success_id;
+        success_name = config.emoji.success_name.clone();
     }
 
     let options = discordhelpers::build_menu_controls();
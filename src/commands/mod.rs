@@ -9,3 +9,10 @@ pub mod block;
 pub mod unblock;
 pub mod invite;
 pub mod cpp;
+pub mod c;
+pub mod py;
+pub mod js;
+pub mod stats;
+pub mod vote;
+pub mod admin;
+pub mod announcements;
@@ -0,0 +1,17 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::cppeval::scripteval::PythonEval;
+use crate::utls::discordhelpers;
+use crate::utls::discordhelpers::EditFuture;
+
+#[command]
+#[aliases("python")]
+pub async fn py(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    discordhelpers::run_quick_eval(ctx, msg, PythonEval::new, "py").await
+}
+
+pub fn py_edit(ctx : &Context, content : String, author : User, old : Message) -> EditFuture<'_> {
+    Box::pin(discordhelpers::handle_edit_quick_eval(ctx, content, author, old, PythonEval::new, "py"))
+}
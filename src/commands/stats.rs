@@ -0,0 +1,94 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult, CommandError};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::cache::StatsDatabaseCache;
+use crate::stats::database::StatsPeriod;
+use crate::utls::discordhelpers::embeds;
+
+#[command]
+#[sub_commands(me, guilds)]
+pub async fn stats(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let period = get_period(&mut args)?;
+
+    let (languages, trend) = {
+        let data_read = ctx.data.read().await;
+        let db = get_database(&data_read)?.lock().await;
+        (
+            db.top_languages(period, None, 10).map_err(database_error)?,
+            db.command_trend(period, None).map_err(database_error)?,
+        )
+    };
+
+    let emb = embeds::build_stats_embed(&msg.author, "Top languages", period, &languages, &trend);
+    let mut emb_msg = embeds::embed_message(emb);
+    msg.channel_id.send_message(&ctx.http, |_| &mut emb_msg).await?;
+
+    debug!("Command executed");
+    Ok(())
+}
+
+#[command]
+async fn me(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let period = get_period(&mut args)?;
+
+    let (languages, trend) = {
+        let data_read = ctx.data.read().await;
+        let db = get_database(&data_read)?.lock().await;
+        (
+            db.top_languages(period, Some(msg.author.id.0), 5).map_err(database_error)?,
+            db.command_trend(period, Some(msg.author.id.0)).map_err(database_error)?,
+        )
+    };
+
+    let title = format!("Usage for {}", msg.author.tag());
+    let emb = embeds::build_stats_embed(&msg.author, &title, period, &languages, &trend);
+    let mut emb_msg = embeds::embed_message(emb);
+    msg.channel_id.send_message(&ctx.http, |_| &mut emb_msg).await?;
+
+    debug!("Command executed");
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+async fn guilds(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let period = get_period(&mut args)?;
+
+    let guilds = {
+        let data_read = ctx.data.read().await;
+        let db = get_database(&data_read)?.lock().await;
+        db.top_guilds(period, 10).map_err(database_error)?
+    };
+
+    let emb = embeds::build_guild_stats_embed(&msg.author, period, &guilds);
+    let mut emb_msg = embeds::embed_message(emb);
+    msg.channel_id.send_message(&ctx.http, |_| &mut emb_msg).await?;
+
+    debug!("Command executed");
+    Ok(())
+}
+
+// day, week or month - defaults to week
+fn get_period(args : &mut Args) -> Result<StatsPeriod, CommandError> {
+    if args.is_empty() {
+        return Ok(StatsPeriod::Week);
+    }
+
+    let input = args.single::<String>()?;
+    match StatsPeriod::parse(&input) {
+        Some(p) => Ok(p),
+        None => Err(CommandError::from(format!("Unknown period '{}', try day, week or month", input)))
+    }
+}
+
+fn get_database(data : &TypeMap) -> Result<&<StatsDatabaseCache as TypeMapKey>::Value, CommandError> {
+    match data.get::<StatsDatabaseCache>() {
+        Some(db) => Ok(db),
+        None => Err(CommandError::from("Statistics aren't being recorded on this instance."))
+    }
+}
+
+fn database_error(e : rusqlite::Error) -> CommandError {
+    CommandError::from(format!("Unable to query statistics\n{}", e))
+}
@@ -3,6 +3,7 @@ use serenity::model::prelude::*;
 use serenity::prelude::*;
 
 use crate::cache::BlocklistCache;
+use crate::utls::discordhelpers;
 
 #[command]
 #[owners_only]
@@ -13,11 +14,14 @@ pub async fn unblock(ctx: &Context, msg: &Message, args: Args) -> CommandResult
 
     let arg = args.parse::<u64>()?;
 
-    let data = ctx.data.read().await;
-    let mut blocklist = data.get::<BlocklistCache>().unwrap().write().await;
+    {
+        let data = ctx.data.read().await;
+        let mut blocklist = data.get::<BlocklistCache>().unwrap().write().await;
 
-    blocklist.unblock(arg);
+        blocklist.unblock(arg);
+    }
 
+    discordhelpers::audit_log(ctx, &msg.author, &format!("Unblocked snowflake `{}`", arg)).await;
     msg.channel_id.say(&ctx.http, format!("Unblocked snowflake `{}`", &arg)).await?;
     Ok(())
 }
@@ -0,0 +1,40 @@
+use serenity::framework::standard::{macros::command, Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use crate::cache::{ConfigCache, VoteCache};
+use crate::utls::constants::MAX_OUTPUT_LEN;
+use crate::utls::discordhelpers::embeds;
+
+#[command]
+pub async fn vote(ctx: &Context, msg: &Message, _: Args) -> CommandResult {
+    let emb = {
+        let data = ctx.data.read().await;
+        let votes = data.get::<VoteCache>().unwrap().read().await;
+        let config = data.get::<ConfigCache>().unwrap().read().await;
+        let vote_link = &config.links.discordbots;
+        let attachment_limit = config.limits.attachment_bytes();
+
+        // describe whichever perks are configured
+        let mut perks = Vec::new();
+        if votes.perks.duration > 0 {
+            if votes.perks.skip_ratelimit {
+                perks.push(String::from("No command cooldown"));
+            }
+            if votes.perks.output_limit > MAX_OUTPUT_LEN {
+                perks.push(format!("Program output up to {} characters", votes.perks.output_limit));
+            }
+            if votes.perks.attachment_limit > attachment_limit {
+                perks.push(format!("Attachments up to {} KiB", votes.perks.attachment_limit / 1024));
+            }
+        }
+
+        let user = msg.author.id.0;
+        embeds::build_vote_embed(&msg.author, vote_link, votes.get(user), votes.perks_remaining(user), &perks.join("\n"))
+    };
+
+    let mut emb_msg = embeds::embed_message(emb);
+    msg.channel_id.send_message(&ctx.http, |_| &mut emb_msg).await?;
+
+    Ok(())
+}
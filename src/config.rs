@@ -0,0 +1,478 @@
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::*;
+
+// Everything we can be configured with. Settings are read from config.toml (or CONFIG_FILE),
+// then any environment variable that's set overrides its setting, so an existing .env keeps
+// working without a config file. See config.example.toml and .env.example.
+
+static DEFAULT_PATH: &str = "config.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub bot: BotConfig,
+    pub emoji: EmojiConfig,
+    pub links: LinkConfig,
+    pub logs: LogConfig,
+    pub limits: LimitConfig,
+    pub ratelimit: RateLimitConfig,
+    pub concurrency: ConcurrencyConfig,
+    pub webhook: WebhookConfig,
+    pub botlists: BotListConfig,
+    pub stats: StatsConfig,
+    pub votes: VoteConfig,
+
+    // filled in once we've connected, never read from disk
+    #[serde(skip)]
+    pub runtime: RuntimeInfo,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct BotConfig {
+    pub token: String,
+    pub prefix: String,
+    // only used if discord won't give us our application info
+    pub id: Option<u64>,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct EmojiConfig {
+    pub success_id: u64,
+    pub success_name: String,
+    pub loading_id: u64,
+    pub loading_name: String,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct LinkConfig {
+    pub invite: String,
+    pub discordbots: String,
+    pub github: String,
+    pub stats: String,
+}
+
+/// Channel ids we log to, each is optional
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct LogConfig {
+    pub join: Option<u64>,
+    pub compile: Option<u64>,
+    pub vote: Option<u64>,
+    pub audit: Option<u64>,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LimitConfig {
+    // in KiB
+    pub attachment_size: u64,
+    // hosts we'll fetch code from when given a link
+    pub url_allow_list: Vec<String>,
+}
+
+impl Default for LimitConfig {
+    fn default() -> Self {
+        LimitConfig {
+            attachment_size: 512,
+            url_allow_list: vec![
+                String::from("pastebin.com"),
+                String::from("gist.githubusercontent.com"),
+                String::from("hastebin.com"),
+                String::from("raw.githubusercontent.com"),
+            ],
+        }
+    }
+}
+
+impl LimitConfig {
+    pub fn attachment_bytes(&self) -> u64 {
+        self.attachment_size * 1024
+    }
+}
+
+/// Our nospam limits, see utls/ratelimit.rs
+#[derive(Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub commands: Vec<String>,
+    // seconds between two commands
+    pub delay: u64,
+    // at most `limit` commands in `time_span` seconds
+    pub time_span: u64,
+    pub limit: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            commands: vec![String::from("compile"), String::from("asm")],
+            delay: 3,
+            time_span: 10,
+            limit: 3,
+        }
+    }
+}
+
+/// How many compilations we run at once, see utls/concurrency.rs
+#[derive(Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    pub global: usize,
+    pub wandbox: usize,
+    pub godbolt: usize,
+    // requests waiting for a slot before we turn new ones away
+    pub queue_size: usize,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        ConcurrencyConfig {
+            global: 16,
+            wandbox: 10,
+            godbolt: 10,
+            queue_size: 50,
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(default)]
+pub struct WebhookConfig {
+    // 0 disables our webhook server
+    pub port: u16,
+    pub address: IpAddr,
+    pub metrics: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            port: 0,
+            address: IpAddr::from([0, 0, 0, 0]),
+            metrics: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct BotListConfig {
+    pub dbl_token: Option<String>,
+    pub dbl_webhook_password: Option<String>,
+    pub dbotsgg_token: Option<String>,
+    pub discordbotlist_token: Option<String>,
+    pub discordbotlist_webhook_password: Option<String>,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct StatsConfig {
+    pub api_link: Option<String>,
+    pub api_key: Option<String>,
+    // sqlite database for ;stats
+    pub database: Option<String>,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(default)]
+pub struct VoteConfig {
+    // how long perks last after a vote, 0 disables them
+    pub perk_hours: u64,
+    pub skip_ratelimit: bool,
+    pub output_len: usize,
+    // in KiB
+    pub attachment_limit: u64,
+}
+
+impl Default for VoteConfig {
+    fn default() -> Self {
+        VoteConfig {
+            perk_hours: 24,
+            skip_ratelimit: true,
+            output_len: 750,
+            attachment_limit: 2048,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RuntimeInfo {
+    pub bot_id: u64,
+    pub avatar: String,
+    // variables we've set from .env, see apply_dotenv
+    pub env_keys: HashSet<String>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    errors: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for error in &self.errors {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn path() -> String {
+        env::var("CONFIG_FILE").unwrap_or_else(|_| String::from(DEFAULT_PATH))
+    }
+
+    pub fn load() -> Result<Config, ConfigError> {
+        let path = Config::path();
+
+        let mut config = if std::path::Path::new(&path).exists() {
+            let contents = fs::read_to_string(&path).map_err(|e| ConfigError {
+                errors: vec![format!("Unable to read {}: {}", path, e)],
+            })?;
+            toml::from_str::<Config>(&contents).map_err(|e| ConfigError {
+                errors: vec![format!("Unable to parse {}: {}", path, e)],
+            })?
+        } else {
+            Config::default()
+        };
+
+        let mut errors = Vec::new();
+        config.apply_env(&mut errors);
+        config.validate(&mut errors);
+        if !errors.is_empty() {
+            return Err(ConfigError { errors });
+        }
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, errors: &mut Vec<String>) {
+        env_string("BOT_TOKEN", &mut self.bot.token);
+        env_string("BOT_PREFIX", &mut self.bot.prefix);
+        env_parse_option("BOT_ID", &mut self.bot.id, errors);
+
+        env_parse("SUCCESS_EMOJI_ID", &mut self.emoji.success_id, errors);
+        env_string("SUCCESS_EMOJI_NAME", &mut self.emoji.success_name);
+        env_parse("LOADING_EMOJI_ID", &mut self.emoji.loading_id, errors);
+        env_string("LOADING_EMOJI_NAME", &mut self.emoji.loading_name);
+
+        env_string("INVITE_LINK", &mut self.links.invite);
+        env_string("DISCORDBOTS_LINK", &mut self.links.discordbots);
+        env_string("GITHUB_LINK", &mut self.links.github);
+        env_string("STATS_LINK", &mut self.links.stats);
+
+        env_parse_option("JOIN_LOG", &mut self.logs.join, errors);
+        env_parse_option("COMPILE_LOG", &mut self.logs.compile, errors);
+        env_parse_option("VOTE_CHANNEL", &mut self.logs.vote, errors);
+        env_parse_option("AUDIT_LOG", &mut self.logs.audit, errors);
+
+        env_parse("ATTACHMENT_SIZE_LIMIT", &mut self.limits.attachment_size, errors);
+        env_list("URL_ALLOW_LIST", &mut self.limits.url_allow_list);
+
+        env_list("RATELIMIT_COMMANDS", &mut self.ratelimit.commands);
+        env_parse("RATELIMIT_DELAY", &mut self.ratelimit.delay, errors);
+        env_parse("RATELIMIT_TIME_SPAN", &mut self.ratelimit.time_span, errors);
+        env_parse("RATELIMIT_LIMIT", &mut self.ratelimit.limit, errors);
+
+        env_parse("CONCURRENCY_GLOBAL", &mut self.concurrency.global, errors);
+        env_parse("CONCURRENCY_WANDBOX", &mut self.concurrency.wandbox, errors);
+        env_parse("CONCURRENCY_GODBOLT", &mut self.concurrency.godbolt, errors);
+        env_parse("QUEUE_SIZE", &mut self.concurrency.queue_size, errors);
+
+        env_parse("DBL_WEBHOOK_PORT", &mut self.webhook.port, errors);
+        env_parse("DBL_WEBHOOK_ADDRESS", &mut self.webhook.address, errors);
+        env_bool("METRICS_ENABLED", &mut self.webhook.metrics);
+
+        env_option("DBL_TOKEN", &mut self.botlists.dbl_token);
+        env_option("DBL_WEBHOOK_PASSWORD", &mut self.botlists.dbl_webhook_password);
+        env_option("DBOTSGG_TOKEN", &mut self.botlists.dbotsgg_token);
+        env_option("DISCORDBOTLIST_TOKEN", &mut self.botlists.discordbotlist_token);
+        env_option("DISCORDBOTLIST_WEBHOOK_PASSWORD", &mut self.botlists.discordbotlist_webhook_password);
+
+        env_option("STATS_API_LINK", &mut self.stats.api_link);
+        env_option("STATS_API_KEY", &mut self.stats.api_key);
+        env_option("STATS_DATABASE", &mut self.stats.database);
+
+        env_parse("VOTE_PERK_HOURS", &mut self.votes.perk_hours, errors);
+        env_bool("VOTE_PERK_SKIP_RATELIMIT", &mut self.votes.skip_ratelimit);
+        env_parse("VOTE_PERK_OUTPUT_LEN", &mut self.votes.output_len, errors);
+        env_parse("VOTE_PERK_ATTACHMENT_LIMIT", &mut self.votes.attachment_limit, errors);
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        let mut require = |missing: bool, setting: &str, var: &str| {
+            if missing {
+                errors.push(format!("{} (or {}) must be set", setting, var));
+            }
+        };
+
+        require(self.bot.token.is_empty(), "bot.token", "BOT_TOKEN");
+        require(self.bot.prefix.is_empty(), "bot.prefix", "BOT_PREFIX");
+        require(self.emoji.success_id == 0, "emoji.success_id", "SUCCESS_EMOJI_ID");
+        require(self.emoji.success_name.is_empty(), "emoji.success_name", "SUCCESS_EMOJI_NAME");
+        require(self.emoji.loading_id == 0, "emoji.loading_id", "LOADING_EMOJI_ID");
+        require(self.emoji.loading_name.is_empty(), "emoji.loading_name", "LOADING_EMOJI_NAME");
+        require(self.links.invite.is_empty(), "links.invite", "INVITE_LINK");
+        require(self.links.discordbots.is_empty(), "links.discordbots", "DISCORDBOTS_LINK");
+        require(self.links.github.is_empty(), "links.github", "GITHUB_LINK");
+        require(self.links.stats.is_empty(), "links.stats", "STATS_LINK");
+
+        if self.ratelimit.limit == 0 {
+            errors.push(String::from("ratelimit.limit (RATELIMIT_LIMIT) must be at least 1"));
+        }
+        if self.concurrency.global == 0 || self.concurrency.wandbox == 0 || self.concurrency.godbolt == 0 {
+            errors.push(String::from("concurrency limits (CONCURRENCY_GLOBAL, CONCURRENCY_WANDBOX, CONCURRENCY_GODBOLT) must be at least 1"));
+        }
+        if self.stats.api_link.is_some() != self.stats.api_key.is_some() {
+            errors.push(String::from("stats.api_link and stats.api_key (STATS_API_LINK, STATS_API_KEY) must be set together"));
+        }
+    }
+
+    // Settings that changed between two configs but are only read at startup
+    pub fn restart_required(&self, other: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.bot != other.bot {
+            changed.push("bot");
+        }
+        if self.logs.vote != other.logs.vote {
+            changed.push("logs.vote");
+        }
+        if self.webhook != other.webhook {
+            changed.push("webhook");
+        }
+        if self.botlists != other.botlists {
+            changed.push("botlists");
+        }
+        if self.stats != other.stats {
+            changed.push("stats");
+        }
+        changed
+    }
+}
+
+/** .env **/
+// Sets the variables in .env, returning their keys. Like dotenv::dotenv, variables we were
+// started with take priority over the file. `previous` is what the last call returned, keys
+// that have since been taken out of the file are removed again.
+pub fn apply_dotenv(previous: &HashSet<String>) -> HashSet<String> {
+    match dotenv::dotenv_iter() {
+        Ok(iter) => apply_env_vars(iter.flatten(), previous),
+        Err(_) => apply_env_vars(std::iter::empty(), previous),
+    }
+}
+
+fn apply_env_vars<I: Iterator<Item = (String, String)>>(vars: I, previous: &HashSet<String>) -> HashSet<String> {
+    let mut applied = HashSet::new();
+    for (key, value) in vars {
+        if env::var_os(&key).is_some() && !previous.contains(&key) {
+            continue;
+        }
+        env::set_var(&key, value);
+        applied.insert(key);
+    }
+
+    for key in previous.difference(&applied) {
+        env::remove_var(key);
+    }
+    applied
+}
+
+/** Environment overrides **/
+// Empty variables are treated as unset, .env.example leaves most of them blank
+
+fn env_value(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+fn env_string(key: &str, field: &mut String) {
+    if let Some(value) = env_value(key) {
+        *field = value;
+    }
+}
+
+fn env_option(key: &str, field: &mut Option<String>) {
+    if let Some(value) = env_value(key) {
+        *field = Some(value);
+    }
+}
+
+// comma separated
+fn env_list(key: &str, field: &mut Vec<String>) {
+    if let Some(value) = env_value(key) {
+        *field = value.split(',').map(|v| String::from(v.trim())).filter(|v| !v.is_empty()).collect();
+    }
+}
+
+fn env_bool(key: &str, field: &mut bool) {
+    if let Some(value) = env_value(key) {
+        *field = value == "1" || value == "true";
+    }
+}
+
+fn env_parse<T: FromStr>(key: &str, field: &mut T, errors: &mut Vec<String>) {
+    if let Some(value) = env_value(key) {
+        match value.parse::<T>() {
+            Ok(v) => *field = v,
+            Err(_) => errors.push(format!("{} has an invalid value '{}'", key, value)),
+        }
+    }
+}
+
+fn env_parse_option<T: FromStr>(key: &str, field: &mut Option<T>, errors: &mut Vec<String>) {
+    if let Some(value) = env_value(key) {
+        match value.parse::<T>() {
+            Ok(v) => *field = Some(v),
+            Err(_) => errors.push(format!("{} has an invalid value '{}'", key, value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs.iter().map(|(k, v)| (String::from(*k), String::from(*v))).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn env_vars_reload() {
+        env::set_var("CONFIG_TEST_STARTED_WITH", "outside");
+        env::remove_var("CONFIG_TEST_KEPT");
+        env::remove_var("CONFIG_TEST_REMOVED");
+
+        let applied = apply_env_vars(vars(&[
+            ("CONFIG_TEST_STARTED_WITH", "file"),
+            ("CONFIG_TEST_KEPT", "1"),
+            ("CONFIG_TEST_REMOVED", "1"),
+        ]), &HashSet::new());
+        assert_eq!(env::var("CONFIG_TEST_STARTED_WITH").unwrap(), "outside");
+        assert_eq!(env::var("CONFIG_TEST_KEPT").unwrap(), "1");
+        assert_eq!(env::var("CONFIG_TEST_REMOVED").unwrap(), "1");
+        assert!(!applied.contains("CONFIG_TEST_STARTED_WITH"));
+
+        // our own keys follow the file, including being taken out of it
+        let applied = apply_env_vars(vars(&[
+            ("CONFIG_TEST_STARTED_WITH", "file"),
+            ("CONFIG_TEST_KEPT", "2"),
+        ]), &applied);
+        assert_eq!(env::var("CONFIG_TEST_STARTED_WITH").unwrap(), "outside");
+        assert_eq!(env::var("CONFIG_TEST_KEPT").unwrap(), "2");
+        assert!(env::var_os("CONFIG_TEST_REMOVED").is_none());
+
+        apply_env_vars(std::iter::empty(), &applied);
+        assert!(env::var_os("CONFIG_TEST_KEPT").is_none());
+        assert_eq!(env::var("CONFIG_TEST_STARTED_WITH").unwrap(), "outside");
+    }
+}
@@ -0,0 +1,189 @@
+use crate::cppeval::eval::EvalError;
+use crate::cppeval::lexer::Lexer;
+use crate::cppeval::quickeval::QuickEval;
+use crate::cppeval::snippet::Snippet;
+
+// C flavour of CppEval, `;c << x << "\n"` becomes a printf per operand with the
+// format picked by _Generic
+pub struct CEval {
+    snippet : Snippet
+}
+
+impl CEval {
+    pub fn new(input : &str) -> CEval {
+        CEval {
+            snippet: Snippet::new(input)
+        }
+    }
+
+    fn do_user_handled(& mut self) -> Result<(), EvalError> {
+        let lexer = Lexer::new(&self.snippet.input)?;
+        if let Some((start, end)) = lexer.find_main() {
+            self.snippet.push_user_main(start, end);
+        }
+        else {
+            return Err(EvalError::new("No main() specified. Invalid request"))
+        }
+
+        Ok(())
+    }
+
+    fn do_statements(& mut self) -> Result<(), EvalError>  {
+        let lexer = Lexer::new(&self.snippet.input)?;
+        let end = match lexer.matching_brace(0) {
+            Some(end) => end,
+            None => return Err(EvalError::new("Parsing failure, detected unbalanced curly-brackets."))
+        };
+
+        self.snippet.push_rest(end+1);
+        self.snippet.build_main(1, end);
+
+        Ok(())
+    }
+
+    fn do_prints(& mut self) -> Result<(), EvalError> {
+        let lexer = Lexer::new(&self.snippet.input)?;
+        let statement_end = lexer.top_level_semicolon();
+        let end = statement_end.unwrap_or(self.snippet.input.len());
+        let operands = lexer.split_shifts(end);
+
+        if let Some(statement_end) = statement_end {
+            self.snippet.push_rest(statement_end+1);
+        }
+        self.snippet.push_generated("\nint main (void) {\n");
+        for (start, end) in operands {
+            let operand = &self.snippet.input[start..end];
+            let start = start + (operand.len() - operand.trim_start().len());
+            let end = start + operand.trim().len();
+            if start >= end {
+                return Err(EvalError::new("Parsing failure, expected an expression after `<<`."));
+            }
+
+            self.snippet.push_generated("QE_PRINT(");
+            self.snippet.push_user(start, end);
+            self.snippet.push_generated(");\n");
+        }
+        self.snippet.push_generated("}");
+        Ok(())
+    }
+}
+
+impl QuickEval for CEval {
+    fn evaluate(& mut self) -> Result<String, EvalError> {
+        self.snippet.strip_inline_code();
+        self.snippet.push_generated(include_str!("c_prelude.in"));
+        self.snippet.push_generated("\n");
+
+        if self.snippet.input.starts_with('{') { // parsing a statement here
+            self.do_statements()?;
+        }
+        else if self.snippet.input.starts_with("<<") { // just outputting
+            self.do_prints()?;
+        }
+        else { // they're handling their own main
+            self.do_user_handled()?;
+        }
+
+        Ok(self.snippet.output())
+    }
+
+    fn language(&self) -> &'static str {
+        "c"
+    }
+
+    fn highlight(&self) -> &'static str {
+        "c"
+    }
+
+    fn translate_diagnostics(&self, diagnostics : &str) -> String {
+        self.snippet.translate_diagnostics(diagnostics, "prog.c")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static PRELUDE : &str = include_str!("c_prelude.in");
+
+    fn evaluate(src : &str) -> String {
+        CEval::new(src).evaluate().unwrap_or_else(|e| panic!("Unable to evaluate {:?}: {}", src, e))
+    }
+
+    // the generated program after our prelude
+    fn program(src : &str) -> String {
+        let output = evaluate(src);
+        assert!(output.starts_with(PRELUDE), "prelude for {:?}", src);
+        output[PRELUDE.len()..].trim().to_owned()
+    }
+
+    #[test]
+    fn prints() {
+        let cases : &[(&str, &str)] = &[
+            ("<< 1", "QE_PRINT(1);"),
+            ("<< x << \"\\n\"", "QE_PRINT(x);\nQE_PRINT(\"\\n\");"),
+            ("<< (1 << 2) << ' '", "QE_PRINT((1 << 2));\nQE_PRINT(' ');"),
+            ("<< f(1, 2)", "QE_PRINT(f(1, 2));"),
+            ("`<< 1`", "QE_PRINT(1);"),
+        ];
+
+        for (src, expected) in cases {
+            assert_eq!(program(src), format!("int main (void) {{\n{}\n}}", expected), "program for {:?}", src);
+        }
+    }
+
+    #[test]
+    fn prints_with_declarations() {
+        assert_eq!(
+            program("<< f(); int f(void) { return 1; }"),
+            "int f(void) { return 1; }\nint main (void) {\nQE_PRINT(f());\n}"
+        );
+    }
+
+    #[test]
+    fn statements_and_main() {
+        assert_eq!(program("{ puts(\"hi\"); }"), "int main (void) {\n puts(\"hi\"); \n}");
+        assert_eq!(program("int main(void) { return 0; }"), "int main(void) { return 0; }");
+    }
+
+    #[test]
+    fn invalid() {
+        for src in &["<< 1 <<", "<< << 1", "{ puts(\"hi\");", "puts(\"hi\");"] {
+            assert!(CEval::new(src).evaluate().is_err(), "{:?} should fail", src);
+        }
+    }
+
+    // Compiles our programs with the local c compiler and checks each operand is printed
+    // with the format for its type, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn print_formats() {
+        let cases : &[(&str, &str)] = &[
+            ("<< 1 << \" \" << -2L << \" \" << 3000000000u", "1 -2 3000000000"),
+            // character literals are ints in C
+            ("<< 'a' << (char)'a'", "97a"),
+            ("<< 2.5 << \" \" << 1.0f", "2.5 1"),
+            ("<< \"hi\" << (char)'c'", "hic"),
+            ("<< (unsigned char)200 << \" \" << (short)-1 << \" \" << (_Bool)5", "200 -1 1"),
+        ];
+
+        let dir = std::env::temp_dir().join(format!("ceval-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (source, binary) = (dir.join("prog.c"), dir.join("prog"));
+
+        for (src, expected) in cases {
+            std::fs::write(&source, evaluate(src)).unwrap();
+            let status = std::process::Command::new("cc")
+                .args(["-std=c11", "-o"])
+                .arg(&binary)
+                .arg(&source)
+                .status()
+                .expect("Unable to run cc");
+            assert!(status.success(), "compiling {:?}", src);
+
+            let output = std::process::Command::new(&binary).output().unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), *expected, "output of {:?}", src);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
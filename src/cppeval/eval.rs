@@ -1,11 +1,18 @@
 use core::fmt;
 
+use wandbox::Wandbox;
+
+use crate::cppeval::lexer::Lexer;
+use crate::cppeval::options::CppOptions;
+use crate::cppeval::quickeval::QuickEval;
+use crate::cppeval::snippet::Snippet;
+
 #[derive(Debug, Clone)]
 pub struct EvalError {
     details: String
 }
 impl EvalError {
-    fn new(msg: &str) -> EvalError {
+    pub fn new(msg: &str) -> EvalError {
         EvalError{details: msg.to_string()}
     }
 }
@@ -21,36 +28,97 @@ impl std::error::Error for EvalError {
 }
 
 pub struct CppEval {
-    input : String,
-    output : String
+    raw : String,
+    options : CppOptions,
+    snippet : Snippet
 }
 
 impl CppEval {
     pub fn new(input : &str) -> CppEval {
         CppEval {
-            input: input.trim().to_owned(),
-            output : String::default()
+            raw: input.trim().to_owned(),
+            options: CppOptions::default(),
+            snippet: Snippet::new("")
         }
     }
 
-    pub fn evaluate(& mut self) -> Result<String, EvalError> {
-        // allow inline code
-        if self.input.starts_with('`') && self.input.ends_with('`') {
-            self.input.remove(0);
-            self.input.remove(self.input.len()-1);
-            self.input = self.input.trim().to_string();
+    fn do_user_handled(& mut self) -> Result<(), EvalError> {
+        let lexer = Lexer::new(&self.snippet.input)?;
+        if let Some((start, end)) = lexer.find_main() {
+            self.snippet.push_user_main(start, end);
         }
+        else {
+            return Err(EvalError::new("No main() specified. Invalid request"))
+
+        }
+
+        Ok(())
+    }
+
+    fn do_statements(& mut self) -> Result<(), EvalError>  {
+        let lexer = Lexer::new(&self.snippet.input)?;
+        let end = match lexer.matching_brace(0) {
+            Some(end) => end,
+            None => return Err(EvalError::new("Parsing failure, detected unbalanced curly-brackets."))
+        };
+
+        self.snippet.push_rest(end+1);
+        self.snippet.build_main(1, end);
+
+        Ok(())
+    }
+
+    fn do_prints(& mut self) -> Result<(), EvalError> {
+        let lexer = Lexer::new(&self.snippet.input)?;
+        let end;
+        if let Some(statement_end) = lexer.top_level_semicolon() {
+            self.snippet.push_rest(statement_end+1);
+            end = statement_end;
+        }
+        else {
+            end = self.snippet.input.len();
+        }
+
+        self.snippet.push_generated("\nint main (void) {\ncout ");
+        self.snippet.push_user(0, end);
+        self.snippet.push_generated(";\n}");
+        Ok(())
+    }
+
+    fn add_headers(& mut self) {
+        self.snippet.push_generated("#include <bits/stdc++.h>\n");
+        self.snippet.push_generated("using namespace std;\n");
+        self.add_ostreaming();
+    }
+
+    fn add_ostreaming(& mut self) {
+        let vec_print = include_str!("more_ostreaming.in");
+        self.snippet.push_generated(vec_print);
+        self.snippet.push_generated("\n\n");
+    }
+}
+
+impl QuickEval for CppEval {
+    fn evaluate(& mut self) -> Result<String, EvalError> {
+        // geordi-style options come before the snippet
+        let (options, rest) = CppOptions::parse(&self.raw)?;
+        self.options = options;
+        self.snippet = Snippet::new(rest);
+        self.snippet.strip_inline_code();
 
         // add bits we need for every request
         self.add_headers();
+        self.snippet.end_prelude();
 
-        if self.input.starts_with('{') { // parsing a statement here
+        if self.snippet.input.starts_with('{') { // parsing a statement here
             if let Err(e) = self.do_statements() {
                 return Err(e)
             }
         }
-        else if self.input.starts_with("<<") { // just outputting
-            self.do_prints();
+        else if self.snippet.input.starts_with("<<") { // just outputting
+            if let Err(e) = self.do_prints() {
+                return Err(e)
+            }
         }
         else { // they're handling their own main
             if let Err(e) = self.do_user_handled() {
@@ -58,106 +126,127 @@ impl CppEval {
             }
         }
 
+        Ok(self.snippet.output())
+    }
 
-        Ok(self.output.clone())
+    fn language(&self) -> &'static str {
+        "c++"
     }
 
-    fn do_user_handled(& mut self) -> Result<(), EvalError> {
-        let re = regex::Regex::new(r"(([a-zA-Z]*?)[\s]+main\((.*?)\)[\s]+\{[\s\S]*?\})").unwrap();
-        if let Some(capture) = re.captures_iter(&self.input).next() {
-            let main = capture[1].trim().to_string();
-            let rest = self.input.replacen(&main, "", 1).trim().to_string();
+    fn highlight(&self) -> &'static str {
+        "cpp"
+    }
+
+    fn compiler_args(&self) -> Vec<String> {
+        self.options.to_args()
+    }
 
-            self.output.push_str(&format!("{}\n", rest));
-            self.output.push_str(&format!("{}\n", main));
+    fn resolve_compiler(&self, wbox : &Wandbox) -> Result<String, EvalError> {
+        self.options.resolve_compiler(wbox)
+    }
+
+    fn shown_source(&self) -> Option<String> {
+        if self.options.show_source || self.options.dry_run {
+            Some(self.snippet.source(self.options.show_prelude))
         }
         else {
-            return Err(EvalError::new("No main() specified. Invalid request"))
-
+            None
         }
+    }
 
-        Ok(())
+    fn dry_run(&self) -> bool {
+        self.options.dry_run
     }
 
-    fn do_statements(& mut self) -> Result<(), EvalError>  {
-        let end = self.get_statement_end();
-        if end == 0 {
-            return Err(EvalError::new("Parsing failure, detected unbalanced curly-brackets."))
-        }
+    fn translate_diagnostics(&self, diagnostics : &str) -> String {
+        self.snippet.translate_diagnostics(diagnostics, "prog.cc")
+    }
+}
 
-        self.do_rest(end+1);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let statements = self.input[1..end].to_owned();
-        self.build_main(&statements);
+    static PRELUDE : &str = include_str!("more_ostreaming.in");
 
-        Ok(())
+    fn evaluate(src : &str) -> String {
+        CppEval::new(src).evaluate().unwrap_or_else(|e| panic!("Unable to evaluate {:?}: {}", src, e))
     }
 
-    fn get_statement_end(&self) -> usize {
-        let mut balance = 0;
-        let mut stop_idx = 0;
-        let mut ignore = false;
-        let mut last = '\0';
-        for (index, char) in self.input.chars().enumerate() {
-            // prevent }  in print statements from messing up our balance, we keep track of the
-            // last character parsed in order to detect escaped quotes that way we know which
-            // quotes indicate the start of a string and which ones do not.
-            if (char == '\'' || char == '"') && last != '\\' {
-                ignore = !ignore;
-            }
-            if ignore && last != '\\' {
-                last = char;
-                continue;
-            }
-            // balance our braces
-            if char == '{' {
-                balance += 1;
-            }
-            if char == '}' {
-                balance -= 1;
-            }
-            if balance == 0 {
-                stop_idx = index;
-                break;
-            }
-            last = char;
-        }
-        stop_idx
-    }
-
-    fn do_rest(& mut self, start_idx : usize) {
-        let rest = &self.input[start_idx..];
-        self.output.push_str(rest.trim());
+    // the generated program after our headers & helpers
+    fn program(src : &str) -> String {
+        let output = evaluate(src);
+        let start = output.find(PRELUDE).expect("prelude missing") + PRELUDE.len();
+        output[start..].trim().to_owned()
     }
 
-    fn do_prints(& mut self) {
-        let input;
-        if let Some(statement_end) = self.input.find(';') {
-            self.do_rest(statement_end+1);
-
-            input = self.input[..statement_end].to_owned();
+    #[test]
+    fn prelude() {
+        for src in &["<< 1", "{ cout << 1; }", "int main() { cout << 1; }"] {
+            let output = evaluate(src);
+            assert!(output.starts_with("#include <bits/stdc++.h>\nusing namespace std;\n"), "headers for {:?}", src);
+            assert_eq!(output.matches(PRELUDE).count(), 1, "prelude for {:?}", src);
         }
-        else {
-            input = self.input.clone();
-        }
-        self.build_main(&format!("cout {};", input));
     }
 
-    fn add_headers(& mut self) {
-        self.output.push_str("#include <bits/stdc++.h>\n");
-        self.output.push_str("using namespace std;\n");
-        //self.add_ostreaming();
+    #[test]
+    fn prints() {
+        let cases : &[(&str, &str)] = &[
+            ("<< vector{1,2,3}", "cout << vector{1,2,3};"),
+            ("<< map<int, int>{{1, 2}, {3, 4}}", "cout << map<int, int>{{1, 2}, {3, 4}};"),
+            ("<< pair{1, 'a'}", "cout << pair{1, 'a'};"),
+            ("<< tuple{1, 2.5, \"x\"s}", "cout << tuple{1, 2.5, \"x\"s};"),
+            ("<< optional<int>{}", "cout << optional<int>{};"),
+            ("<< variant<int, string>{\"hi\"s}", "cout << variant<int, string>{\"hi\"s};"),
+            ("`<< vector{1,2,3}`", "cout << vector{1,2,3};"),
+        ];
+
+        for (src, expected) in cases {
+            assert_eq!(program(src), format!("int main (void) {{\n{}\n}}", expected), "program for {:?}", src);
+        }
     }
 
-    fn build_main(& mut self, statements : &str) {
-        self.output.push_str(&format!("\nint main (void) {{\n{}\n}}", statements));
-
+    #[test]
+    fn prints_with_declarations() {
+        assert_eq!(
+            program("<< f(); int f() { return 1; }"),
+            "int f() { return 1; }\nint main (void) {\ncout << f();\n}"
+        );
     }
 
-/*    fn add_ostreaming(& mut self) {
-        let vec_print = include_str!("more_ostreaming.in");
-        self.output.push_str(vec_print);
-        self.output.push_str("\n\n");
+    // Compiles our programs with the local c++ compiler and checks what they print,
+    // run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn prelude_output() {
+        let cases : &[(&str, &str)] = &[
+            ("<< vector{1, 2, 3}", "{1, 2, 3}"),
+            ("<< map<int, int>{{1, 2}, {3, 4}}", "{(1, 2), (3, 4)}"),
+            ("<< pair{1, 'a'}", "(1, a)"),
+            ("<< tuple{1, 2.5, \"x\"s}", "(1, 2.5, x)"),
+            ("<< optional<int>{} << ' ' << optional<int>{5}", "nullopt 5"),
+            ("<< variant<int, string>{\"hi\"s}", "hi"),
+            ("<< vector<pair<int, string>>{{1, \"a\"}}", "{(1, a)}"),
+            ("<< \"abc\"s", "abc"),
+        ];
+
+        let dir = std::env::temp_dir().join(format!("cppeval-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (source, binary) = (dir.join("prog.cc"), dir.join("prog"));
+
+        for (src, expected) in cases {
+            std::fs::write(&source, evaluate(src)).unwrap();
+            let status = std::process::Command::new("c++")
+                .args(["-std=gnu++2a", "-o"])
+                .arg(&binary)
+                .arg(&source)
+                .status()
+                .expect("Unable to run c++");
+            assert!(status.success(), "compiling {:?}", src);
+
+            let output = std::process::Command::new(&binary).output().unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), *expected, "output of {:?}", src);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
     }
-*/
-}
\ No newline at end of file
+}
@@ -0,0 +1,402 @@
+use crate::cppeval::eval::EvalError;
+
+// A very small C++ lexer, it only knows enough to tell code apart from comments, string
+// literals and character literals. That's all we need to find statement boundaries and
+// matching braces without getting confused by something like `cout << "}";`
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TokenKind {
+    Identifier,
+    Number,
+    Literal,
+    Punctuation,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    // byte offsets into the source
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct Lexer<'a> {
+    src: &'a str,
+    tokens: Vec<Token>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src : &'a str) -> Result<Lexer<'a>, EvalError> {
+        let tokens = tokenize(src)?;
+        Ok(Lexer { src, tokens })
+    }
+
+    pub fn text(&self, token : &Token) -> &'a str {
+        &self.src[token.start..token.end]
+    }
+
+    fn is_punct(&self, token : &Token, c : &str) -> bool {
+        token.kind == TokenKind::Punctuation && self.text(token) == c
+    }
+
+    // Given the index of an opening bracket token, finds the index of the token that closes it
+    fn matching_token(&self, open : usize) -> Option<usize> {
+        let (open_str, close_str) = match self.text(&self.tokens[open]) {
+            "{" => ("{", "}"),
+            "(" => ("(", ")"),
+            "[" => ("[", "]"),
+            _ => return None
+        };
+
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(open) {
+            if self.is_punct(token, open_str) {
+                depth += 1;
+            }
+            else if self.is_punct(token, close_str) {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+        }
+        None
+    }
+
+    // Byte offset of the brace closing the one at `open_offset`
+    pub fn matching_brace(&self, open_offset : usize) -> Option<usize> {
+        let open = self.tokens.iter().position(|t| t.start == open_offset)?;
+        let close = self.matching_token(open)?;
+        Some(self.tokens[close].start)
+    }
+
+    // Byte offset of the first `;` that isn't nested in any brackets
+    pub fn top_level_semicolon(&self) -> Option<usize> {
+        let mut depth = 0i32;
+        for token in &self.tokens {
+            if token.kind != TokenKind::Punctuation {
+                continue;
+            }
+            match self.text(token) {
+                "{" | "(" | "[" => depth += 1,
+                "}" | ")" | "]" => depth -= 1,
+                ";" if depth == 0 => return Some(token.start),
+                _ => ()
+            }
+        }
+        None
+    }
+
+    // Splits input[..end] on every `<<` that isn't nested in any brackets, returning the
+    // byte ranges of the operands after each one
+    pub fn split_shifts(&self, end : usize) -> Vec<(usize, usize)> {
+        let mut operators = Vec::new();
+        let mut depth = 0i32;
+        let mut last : Option<&Token> = None;
+        for token in self.tokens.iter().take_while(|t| t.end <= end) {
+            if token.kind == TokenKind::Punctuation {
+                match self.text(token) {
+                    "{" | "(" | "[" => depth += 1,
+                    "}" | ")" | "]" => depth -= 1,
+                    "<" if depth == 0 => {
+                        if let Some(prev) = last {
+                            if self.is_punct(prev, "<") && prev.end == token.start {
+                                operators.push((prev.start, token.end));
+                            }
+                        }
+                    }
+                    _ => ()
+                }
+            }
+            last = Some(token);
+        }
+
+        let mut operands = Vec::new();
+        for (i, (_, op_end)) in operators.iter().enumerate() {
+            let operand_end = operators.get(i + 1).map(|(s, _)| *s).unwrap_or(end);
+            operands.push((*op_end, operand_end));
+        }
+        operands
+    }
+
+    // Finds a top-level definition of main, returning the byte range from its return type
+    // to the closing brace of its body
+    pub fn find_main(&self) -> Option<(usize, usize)> {
+        let mut depth = 0i32;
+        for (i, token) in self.tokens.iter().enumerate() {
+            if token.kind == TokenKind::Punctuation {
+                match self.text(token) {
+                    "{" | "(" | "[" => depth += 1,
+                    "}" | ")" | "]" => depth -= 1,
+                    _ => ()
+                }
+                continue;
+            }
+
+            if depth != 0 || token.kind != TokenKind::Identifier || self.text(token) != "main" {
+                continue;
+            }
+
+            // a call to main, or a variable named main, isn't what we're looking for
+            let open = i + 1;
+            match self.tokens.get(open) {
+                Some(t) if self.is_punct(t, "(") => (),
+                _ => continue
+            }
+            let start = match i.checked_sub(1).map(|p| &self.tokens[p]) {
+                Some(t) if t.kind == TokenKind::Identifier => t.start,
+                _ => continue
+            };
+
+            // skip over the parameters and anything like `-> int` or `noexcept` before the body
+            let close = self.matching_token(open)?;
+            let body = self.tokens[close..]
+                .iter()
+                .position(|t| self.is_punct(t, "{") || self.is_punct(t, ";"))
+                .map(|p| p + close)?;
+            if !self.is_punct(&self.tokens[body], "{") {
+                continue;
+            }
+
+            let end = self.matching_token(body)?;
+            return Some((start, self.tokens[end].end));
+        }
+        None
+    }
+}
+
+// identifiers that turn a following quote into a prefixed literal
+static LITERAL_PREFIXES: [&str; 9] = ["R", "u8R", "uR", "UR", "LR", "u8", "u", "U", "L"];
+
+fn tokenize(src : &str) -> Result<Vec<Token>, EvalError> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        // comments
+        if c == b'/' && bytes.get(i + 1) == Some(&b'/') {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            match src[i + 2..].find("*/") {
+                Some(end) => i += end + 4,
+                None => return Err(EvalError::new("Parsing failure, unterminated comment."))
+            }
+            continue;
+        }
+
+        if c.is_ascii_alphabetic() || c == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+
+            let ident = &src[start..i];
+            if i < bytes.len() && (bytes[i] == b'"' || bytes[i] == b'\'') && LITERAL_PREFIXES.contains(&ident) {
+                let raw = ident.ends_with('R') && bytes[i] == b'"';
+                i = if raw {
+                    skip_raw_string(src, i)?
+                } else {
+                    skip_quoted(bytes, i)?
+                };
+                tokens.push(Token { kind: TokenKind::Literal, start, end: i });
+            }
+            else {
+                tokens.push(Token { kind: TokenKind::Identifier, start, end: i });
+            }
+            continue;
+        }
+
+        // numbers may contain digit separators (1'000) and signed exponents (1e-5)
+        if c.is_ascii_digit() || (c == b'.' && bytes.get(i + 1).map_or(false, |b| b.is_ascii_digit())) {
+            i += 1;
+            while i < bytes.len() {
+                let b = bytes[i];
+                if b.is_ascii_alphanumeric() || b == b'_' || b == b'.' {
+                    i += 1;
+                }
+                else if b == b'\'' && bytes.get(i + 1).map_or(false, |n| n.is_ascii_alphanumeric()) {
+                    i += 2;
+                }
+                else if (b == b'+' || b == b'-') && matches!(bytes[i - 1], b'e' | b'E' | b'p' | b'P') {
+                    i += 1;
+                }
+                else {
+                    break;
+                }
+            }
+            tokens.push(Token { kind: TokenKind::Number, start, end: i });
+            continue;
+        }
+
+        if c == b'"' || c == b'\'' {
+            i = skip_quoted(bytes, i)?;
+            tokens.push(Token { kind: TokenKind::Literal, start, end: i });
+            continue;
+        }
+
+        // anything else is punctuation, we only care about single characters
+        let len = src[i..].chars().next().map_or(1, |ch| ch.len_utf8());
+        i += len;
+        tokens.push(Token { kind: TokenKind::Punctuation, start, end: i });
+    }
+
+    Ok(tokens)
+}
+
+// Skips a "string" or 'c' literal starting at the opening quote, returning the offset past it
+fn skip_quoted(bytes : &[u8], open : usize) -> Result<usize, EvalError> {
+    let quote = bytes[open];
+    let mut i = open + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' => break,
+            b if b == quote => return Ok(i + 1),
+            _ => i += 1
+        }
+    }
+
+    if quote == b'"' {
+        Err(EvalError::new("Parsing failure, unterminated string literal."))
+    } else {
+        Err(EvalError::new("Parsing failure, unterminated character literal."))
+    }
+}
+
+// Skips R"delim( ... )delim" starting at the opening quote
+fn skip_raw_string(src : &str, open : usize) -> Result<usize, EvalError> {
+    let after_quote = &src[open + 1..];
+    let paren = match after_quote.find('(') {
+        Some(p) if p <= 16 => p,
+        _ => return Err(EvalError::new("Parsing failure, invalid raw string delimiter."))
+    };
+
+    let terminator = format!("){}\"", &after_quote[..paren]);
+    match after_quote[paren + 1..].find(&terminator) {
+        Some(end) => Ok(open + 1 + paren + 1 + end + terminator.len()),
+        None => Err(EvalError::new("Parsing failure, unterminated raw string literal."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(src : &str) -> Lexer<'_> {
+        Lexer::new(src).unwrap_or_else(|e| panic!("Unable to lex {:?}: {}", src, e))
+    }
+
+    #[test]
+    fn find_main() {
+        let cases : &[(&str, Option<&str>)] = &[
+            ("int main() { return 0; }", Some("int main() { return 0; }")),
+            ("auto main() -> int { return 0; }", Some("auto main() -> int { return 0; }")),
+            ("int main() { if (x) { y(); } char c = '}'; }", Some("int main() { if (x) { y(); } char c = '}'; }")),
+            ("int main() { puts(\"\\\\\"); }", Some("int main() { puts(\"\\\\\"); }")),
+            ("void f() { puts(\"int main() {}\"); } int main() {}", Some("int main() {}")),
+            ("// int main() {}\nint main() { }", Some("int main() { }")),
+            ("/* int main() {} */ int main() {}", Some("int main() {}")),
+            ("auto s = R\"(int main() {})\"; int main() {}", Some("int main() {}")),
+            ("struct S { int main() { return 1; } }; int main() {}", Some("int main() {}")),
+            ("int x = main();", None),
+            ("int main();", None),
+            ("cout << 1;", None),
+        ];
+
+        for (src, expected) in cases {
+            let lexer = lex(src);
+            let found = lexer.find_main().map(|(start, end)| &src[start..end]);
+            assert_eq!(found, *expected, "find_main({:?})", src);
+        }
+    }
+
+    #[test]
+    fn matching_brace() {
+        let cases : &[(&str, Option<&str>)] = &[
+            ("{ a; }", Some("{ a; }")),
+            ("{ { } { { } } } x", Some("{ { } { { } } }")),
+            ("{ puts(\"}\"); }", Some("{ puts(\"}\"); }")),
+            ("{ char c = '}'; }", Some("{ char c = '}'; }")),
+            ("{ char c = '\"'; } }", Some("{ char c = '\"'; }")),
+            ("{ puts(\"\\\\\"); } }", Some("{ puts(\"\\\\\"); }")),
+            ("{ // }\n } }", Some("{ // }\n }")),
+            ("{ /* } */ } }", Some("{ /* } */ }")),
+            ("{ auto s = R\"x(})\"})x\"; } }", Some("{ auto s = R\"x(})\"})x\"; }")),
+            ("{ unclosed", None),
+        ];
+
+        for (src, expected) in cases {
+            let lexer = lex(src);
+            let open = src.find('{').unwrap();
+            let found = lexer.matching_brace(open).map(|close| &src[open..close + 1]);
+            assert_eq!(found, *expected, "matching_brace({:?})", src);
+        }
+    }
+
+    #[test]
+    fn top_level_semicolon() {
+        let cases : &[(&str, Option<&str>)] = &[
+            ("a; b;", Some("a")),
+            ("for (;;) {} x;", Some("for (;;) {} x")),
+            ("puts(\";\"); x;", Some("puts(\";\")")),
+            ("char c = ';'; x;", Some("char c = ';'")),
+            ("char c = '\"'; x;", Some("char c = '\"'")),
+            ("puts(\"\\\\\"); x;", Some("puts(\"\\\\\")")),
+            ("// ;\nx;", Some("// ;\nx")),
+            ("/* ; */ x;", Some("/* ; */ x")),
+            ("R\"(;)\";", Some("R\"(;)\"")),
+            ("{ a; }", None),
+            ("no semicolon", None),
+        ];
+
+        for (src, expected) in cases {
+            let lexer = lex(src);
+            let found = lexer.top_level_semicolon().map(|end| &src[..end]);
+            assert_eq!(found, *expected, "top_level_semicolon({:?})", src);
+        }
+    }
+
+    #[test]
+    fn split_shifts() {
+        let cases : &[(&str, &[&str])] = &[
+            ("<< 1 << 2", &["1", "2"]),
+            ("<< f(a << b) << c", &["f(a << b)", "c"]),
+            ("<< vector{1 << 2, 3}", &["vector{1 << 2, 3}"]),
+            ("<< \"<<\" << x", &["\"<<\"", "x"]),
+            ("<< '<' << x", &["'<'", "x"]),
+            ("<< a < b", &["a < b"]),
+            ("<< x /* << y */", &["x /* << y */"]),
+            ("< < x", &[]),
+        ];
+
+        for (src, expected) in cases {
+            let lexer = lex(src);
+            let operands : Vec<&str> = lexer
+                .split_shifts(src.len())
+                .into_iter()
+                .map(|(start, end)| src[start..end].trim())
+                .collect();
+            assert_eq!(operands, *expected, "split_shifts({:?})", src);
+        }
+    }
+
+    #[test]
+    fn unterminated() {
+        let cases = ["puts(\"abc);", "char c = 'a;", "/* comment", "R\"(raw\";", "R\"0123456789abcdefg(x)0123456789abcdefg\""];
+        for src in &cases {
+            assert!(Lexer::new(src).is_err(), "Lexer::new({:?}) should fail", src);
+        }
+    }
+}
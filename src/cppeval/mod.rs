@@ -1 +1,8 @@
-pub mod eval;
\ No newline at end of file
+pub mod eval;
+pub mod options;
+pub mod sourcemap;
+pub mod lexer;
+pub mod snippet;
+pub mod quickeval;
+pub mod ceval;
+pub mod scripteval;
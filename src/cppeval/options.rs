@@ -0,0 +1,130 @@
+use wandbox::Wandbox;
+
+use crate::cppeval::eval::EvalError;
+
+static DEFAULT_GCC: &str = "gcc-10.1.0";
+static DEFAULT_STANDARD: &str = "gnu++2a";
+static DEFAULT_OPTIMIZATION: &str = "-O2";
+
+static STANDARDS: [&str; 14] = [
+    "c++98", "c++03", "c++11", "c++14", "c++17", "c++1z", "c++20", "c++2a", "c++23", "c++2b",
+    "gnu++11", "gnu++14", "gnu++17", "gnu++2a",
+];
+
+pub enum CompilerChoice {
+    Gcc,
+    Clang,
+    Named(String),
+}
+
+/// Geordi-style prefixes given before the snippet, e.g. `;cpp -std=c++17 --clang -O0 { ... }`
+pub struct CppOptions {
+    pub compiler: CompilerChoice,
+    pub standard: String,
+    pub optimization: String,
+    pub flags: Vec<String>,
+    // --source shows the generated program with the result, --dry shows it without compiling
+    pub show_source: bool,
+    pub show_prelude: bool,
+    pub dry_run: bool,
+}
+
+impl Default for CppOptions {
+    fn default() -> Self {
+        CppOptions {
+            compiler: CompilerChoice::Gcc,
+            standard: String::from(DEFAULT_STANDARD),
+            optimization: String::from(DEFAULT_OPTIMIZATION),
+            flags: Vec::new(),
+            show_source: false,
+            show_prelude: false,
+            dry_run: false,
+        }
+    }
+}
+
+impl CppOptions {
+    // Pulls our options off the front of the input, returning them along with the remaining snippet
+    pub fn parse(input : &str) -> Result<(CppOptions, &str), EvalError> {
+        let mut options = CppOptions::default();
+
+        let mut rest = input.trim_start();
+        while rest.starts_with('-') {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let arg = &rest[..end];
+
+            if arg == "--clang" {
+                options.compiler = CompilerChoice::Clang;
+            }
+            else if arg == "--gcc" {
+                options.compiler = CompilerChoice::Gcc;
+            }
+            else if arg == "--source" {
+                options.show_source = true;
+            }
+            else if arg == "--prelude" {
+                options.show_prelude = true;
+            }
+            else if arg == "--dry" {
+                options.dry_run = true;
+            }
+            else if let Some(name) = arg.strip_prefix("--compiler=") {
+                options.compiler = CompilerChoice::Named(name.to_owned());
+            }
+            else if let Some(standard) = arg.strip_prefix("-std=") {
+                if !STANDARDS.contains(&standard) {
+                    return Err(EvalError::new(&format!("Unknown c++ standard '{}'", standard)));
+                }
+                options.standard = standard.to_owned();
+            }
+            else if matches!(arg, "-O0" | "-O1" | "-O2" | "-O3" | "-Os" | "-Og" | "-Ofast") {
+                options.optimization = arg.to_owned();
+            }
+            else if arg.starts_with("-W") || arg.starts_with("-f") || arg.starts_with("-D") || arg == "-pedantic" {
+                options.flags.push(arg.to_owned());
+            }
+            else {
+                return Err(EvalError::new(&format!("Unknown option '{}'", arg)));
+            }
+
+            rest = rest[end..].trim_start();
+        }
+
+        Ok((options, rest))
+    }
+
+    // Finds the wandbox compiler we should dispatch to, validating it against the compiler cache
+    pub fn resolve_compiler(&self, wbox : &Wandbox) -> Result<String, EvalError> {
+        let prefix = match &self.compiler {
+            CompilerChoice::Named(name) => {
+                if wbox.is_valid_compiler_str(name) {
+                    return Ok(name.clone());
+                }
+                return Err(EvalError::new(&format!("Unknown compiler '{}'", name)));
+            }
+            CompilerChoice::Gcc => {
+                if wbox.is_valid_compiler_str(DEFAULT_GCC) {
+                    return Ok(String::from(DEFAULT_GCC));
+                }
+                "gcc-"
+            }
+            CompilerChoice::Clang => "clang-",
+        };
+
+        // fall back to the newest release we can find, wandbox lists them newest first
+        let compilers = wbox.get_compilers("c++").unwrap_or_default();
+        for compiler in compilers {
+            if compiler.name.starts_with(prefix) && !compiler.name.contains("head") {
+                return Ok(compiler.name);
+            }
+        }
+
+        Err(EvalError::new("Unable to find a suitable c++ compiler"))
+    }
+
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![self.optimization.clone(), format!("-std={}", self.standard)];
+        args.extend(self.flags.iter().cloned());
+        args
+    }
+}
@@ -0,0 +1,46 @@
+use wandbox::Wandbox;
+
+use crate::cppeval::eval::EvalError;
+
+/// A geordi-style front-end that turns a short snippet into a complete program
+pub trait QuickEval {
+    /// Generates the full program for wandbox
+    fn evaluate(& mut self) -> Result<String, EvalError>;
+
+    /// The wandbox language we'll pick a compiler from
+    fn language(&self) -> &'static str;
+
+    /// The code block language our generated source is highlighted as
+    fn highlight(&self) -> &'static str;
+
+    /// Options passed along to the compiler or interpreter
+    fn compiler_args(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Picks the newest non-head compiler for our language
+    fn resolve_compiler(&self, wbox : &Wandbox) -> Result<String, EvalError> {
+        let compilers = wbox.get_compilers(self.language()).unwrap_or_default();
+        for compiler in compilers {
+            if !compiler.name.contains("head") {
+                return Ok(compiler.name);
+            }
+        }
+        Err(EvalError::new(&format!("Unable to find a suitable {} compiler", self.language())))
+    }
+
+    /// The generated program, if the user asked to see it
+    fn shown_source(&self) -> Option<String> {
+        None
+    }
+
+    /// Only show the generated program, don't compile it
+    fn dry_run(&self) -> bool {
+        false
+    }
+
+    /// Points diagnostics for the generated program back at the user's snippet
+    fn translate_diagnostics(&self, diagnostics : &str) -> String {
+        diagnostics.to_owned()
+    }
+}
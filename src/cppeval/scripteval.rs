@@ -0,0 +1,179 @@
+use crate::cppeval::eval::EvalError;
+use crate::cppeval::quickeval::QuickEval;
+use crate::cppeval::snippet::Snippet;
+
+// REPL-style front-ends for interpreted languages. Rather than try to parse the snippet
+// ourselves, we hand it to the interpreter as a string and let it tell us whether the
+// last thing in it was an expression worth printing.
+
+fn quote(snippet : &Snippet) -> Result<String, EvalError> {
+    if snippet.input.is_empty() {
+        return Err(EvalError::new("Nothing to evaluate. Invalid request"));
+    }
+
+    // JSON strings are valid string literals in both python & javascript
+    serde_json::to_string(&snippet.input)
+        .map_err(|e| EvalError::new(&format!("Unable to encode snippet: {}", e)))
+}
+
+pub struct PythonEval {
+    snippet : Snippet
+}
+
+impl PythonEval {
+    pub fn new(input : &str) -> PythonEval {
+        PythonEval {
+            snippet: Snippet::new(input)
+        }
+    }
+}
+
+impl QuickEval for PythonEval {
+    fn evaluate(& mut self) -> Result<String, EvalError> {
+        self.snippet.strip_inline_code();
+        let source = quote(&self.snippet)?;
+
+        self.snippet.push_generated("import ast as __qe_ast\n");
+        self.snippet.push_generated(&format!("__qe_tree = __qe_ast.parse({}, 'snippet', 'exec')\n", source));
+        self.snippet.push_generated("__qe_last = None\n");
+        self.snippet.push_generated("if __qe_tree.body and isinstance(__qe_tree.body[-1], __qe_ast.Expr):\n");
+        self.snippet.push_generated("    __qe_last = __qe_ast.Expression(__qe_tree.body.pop().value)\n");
+        self.snippet.push_generated("exec(compile(__qe_tree, 'snippet', 'exec'))\n");
+        self.snippet.push_generated("if __qe_last is not None:\n");
+        self.snippet.push_generated("    __qe_value = eval(compile(__qe_last, 'snippet', 'eval'))\n");
+        self.snippet.push_generated("    if __qe_value is not None:\n");
+        self.snippet.push_generated("        print(repr(__qe_value))\n");
+        Ok(self.snippet.output())
+    }
+
+    fn language(&self) -> &'static str {
+        "python"
+    }
+
+    fn highlight(&self) -> &'static str {
+        "py"
+    }
+}
+
+pub struct JavaScriptEval {
+    snippet : Snippet
+}
+
+impl JavaScriptEval {
+    pub fn new(input : &str) -> JavaScriptEval {
+        JavaScriptEval {
+            snippet: Snippet::new(input)
+        }
+    }
+}
+
+impl QuickEval for JavaScriptEval {
+    fn evaluate(& mut self) -> Result<String, EvalError> {
+        self.snippet.strip_inline_code();
+        let source = quote(&self.snippet)?;
+
+        // the snippet runs in the global scope, so give it access to require
+        self.snippet.push_generated("globalThis.require = require;\n");
+        self.snippet.push_generated(&format!("const __qe_value = require('vm').runInThisContext({}, {{ filename: 'snippet' }});\n", source));
+        self.snippet.push_generated("if (__qe_value !== undefined) {\n");
+        self.snippet.push_generated("    console.log(require('util').inspect(__qe_value));\n");
+        self.snippet.push_generated("}\n");
+        Ok(self.snippet.output())
+    }
+
+    fn language(&self) -> &'static str {
+        "javascript"
+    }
+
+    fn highlight(&self) -> &'static str {
+        "js"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate<T : QuickEval>(mut eval : T, src : &str) -> String {
+        eval.evaluate().unwrap_or_else(|e| panic!("Unable to evaluate {:?}: {}", src, e))
+    }
+
+    #[test]
+    fn quoting() {
+        let cases : &[(&str, &str)] = &[
+            ("1 + 1", "\"1 + 1\""),
+            ("print(\"hi\")\nx", "\"print(\\\"hi\\\")\\nx\""),
+            ("`'a' + 'b'`", "\"'a' + 'b'\""),
+            ("s = '\\\\'", "\"s = '\\\\\\\\'\""),
+        ];
+
+        for (src, quoted) in cases {
+            let python = evaluate(PythonEval::new(src), src);
+            assert!(python.contains(&format!("__qe_ast.parse({}, 'snippet', 'exec')", quoted)), "python for {:?}:\n{}", src, python);
+
+            let js = evaluate(JavaScriptEval::new(src), src);
+            assert!(js.contains(&format!("runInThisContext({}, {{ filename: 'snippet' }})", quoted)), "javascript for {:?}:\n{}", src, js);
+        }
+    }
+
+    #[test]
+    fn auto_print() {
+        let python = evaluate(PythonEval::new("1"), "1");
+        assert!(python.contains("isinstance(__qe_tree.body[-1], __qe_ast.Expr)"));
+        assert!(python.ends_with("    if __qe_value is not None:\n        print(repr(__qe_value))\n"));
+
+        let js = evaluate(JavaScriptEval::new("1"), "1");
+        assert!(js.ends_with("if (__qe_value !== undefined) {\n    console.log(require('util').inspect(__qe_value));\n}\n"));
+    }
+
+    #[test]
+    fn empty() {
+        for src in &["", "   ", "``"] {
+            assert!(PythonEval::new(src).evaluate().is_err(), "python {:?} should fail", src);
+            assert!(JavaScriptEval::new(src).evaluate().is_err(), "javascript {:?} should fail", src);
+        }
+    }
+
+    fn run(program : &str, args : &[&str], source : String) -> String {
+        let output = std::process::Command::new(program)
+            .args(args)
+            .arg(source)
+            .output()
+            .unwrap_or_else(|e| panic!("Unable to run {}: {}", program, e));
+        assert!(output.status.success(), "{} failed: {}", program, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    // Runs our programs with the local interpreters, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn python_output() {
+        let cases : &[(&str, &str)] = &[
+            ("1 + 1", "2\n"),
+            ("x = 5", ""),
+            ("x = 5\nx * 2", "10\n"),
+            ("print('hi')\n'a'", "hi\n'a'\n"),
+            ("None", ""),
+            ("[i * i for i in range(3)]", "[0, 1, 4]\n"),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(run("python3", &["-c"], evaluate(PythonEval::new(src), src)), *expected, "output of {:?}", src);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn javascript_output() {
+        let cases : &[(&str, &str)] = &[
+            ("1 + 1", "2\n"),
+            ("let x = 5", ""),
+            ("let x = 5; x * 2", "10\n"),
+            ("console.log('hi'); 'a'", "hi\n'a'\n"),
+            ("[1, 2].map(x => x * 2)", "[ 2, 4 ]\n"),
+            ("require('path').basename('/a/b')", "'b'\n"),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(run("node", &["-e"], evaluate(JavaScriptEval::new(src), src)), *expected, "output of {:?}", src);
+        }
+    }
+}
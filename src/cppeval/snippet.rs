@@ -0,0 +1,92 @@
+use crate::cppeval::sourcemap::SourceMap;
+
+// The program we're generating from a user's snippet. Anything copied from the snippet
+// goes through push_user so we remember where it came from.
+pub struct Snippet {
+    pub input : String,
+    output : String,
+    source_map : SourceMap,
+    // where our headers & helpers end and the user's program begins
+    prelude_end : usize
+}
+
+impl Snippet {
+    pub fn new(input : &str) -> Snippet {
+        Snippet {
+            input: input.trim().to_owned(),
+            output : String::default(),
+            source_map : SourceMap::default(),
+            prelude_end : 0
+        }
+    }
+
+    // allow inline code
+    pub fn strip_inline_code(& mut self) {
+        if self.input.len() > 1 && self.input.starts_with('`') && self.input.ends_with('`') {
+            self.input.remove(0);
+            self.input.remove(self.input.len()-1);
+            self.input = self.input.trim().to_string();
+        }
+    }
+
+    pub fn output(&self) -> String {
+        self.output.clone()
+    }
+
+    // the generated program as we'd show it to the user, prelude and all if they asked for it
+    pub fn source(&self, include_prelude : bool) -> String {
+        if include_prelude || self.prelude_end == 0 {
+            return self.output.clone();
+        }
+        format!("// prelude hidden, use --prelude to show it\n{}", self.output[self.prelude_end..].trim_start())
+    }
+
+    pub fn end_prelude(& mut self) {
+        self.prelude_end = self.output.len();
+    }
+
+    pub fn translate_diagnostics(&self, diagnostics : &str, file_name : &str) -> String {
+        self.source_map.translate_diagnostics(diagnostics, file_name)
+    }
+
+    pub fn push_generated(& mut self, text : &str) {
+        self.output.push_str(text);
+    }
+
+    // copies input[start..end] into our output, remembering where it came from
+    pub fn push_user(& mut self, start : usize, end : usize) {
+        let line = self.output.matches('\n').count();
+        let col = self.output.len() - self.output.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        let text = &self.input[start..end];
+        self.source_map.add(&self.input, start, text, line, col);
+        self.output.push_str(text);
+    }
+
+    // copies everything from start_idx onwards, minus surrounding whitespace
+    pub fn push_rest(& mut self, start_idx : usize) {
+        let rest = &self.input[start_idx..];
+        let start = start_idx + (rest.len() - rest.trim_start().len());
+        let end = start_idx + rest.trim_end().len();
+        if start < end {
+            self.push_user(start, end);
+        }
+    }
+
+    // wraps input[start..end] in a main function
+    pub fn build_main(& mut self, start : usize, end : usize) {
+        self.push_generated("\nint main (void) {\n");
+        self.push_user(start, end);
+        self.push_generated("\n}");
+    }
+
+    // places a user-provided main after everything else so it can use anything they've declared
+    pub fn push_user_main(& mut self, start : usize, end : usize) {
+        self.push_user(0, start);
+        self.push_generated("\n");
+        self.push_user(end, self.input.len());
+        self.push_generated("\n");
+        self.push_user(start, end);
+        self.push_generated("\n");
+    }
+}
@@ -0,0 +1,212 @@
+// Keeps track of where each line of the generated program came from so compiler
+// diagnostics can be pointed back at the user's snippet instead of our prelude.
+
+#[derive(Clone, Copy)]
+struct LineOrigin {
+    // zero-based line in the user's snippet
+    line: usize,
+    // the generated column where the user's text starts, and the snippet column it came from
+    output_col: usize,
+    input_col: usize,
+}
+
+#[derive(Default)]
+pub struct SourceMap {
+    lines: Vec<Option<LineOrigin>>,
+}
+
+impl SourceMap {
+    // Records that `text` from `input` (starting at byte `offset`) was placed in the
+    // generated program at the given zero-based line and column
+    pub fn add(&mut self, input : &str, offset : usize, text : &str, output_line : usize, output_col : usize) {
+        let before = &input[..offset];
+        let input_line = before.matches('\n').count();
+        let input_col = offset - before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        let line_count = text.matches('\n').count() + 1;
+        for i in 0..line_count {
+            let origin = if i == 0 {
+                LineOrigin { line: input_line, output_col, input_col }
+            } else {
+                LineOrigin { line: input_line + i, output_col: 0, input_col: 0 }
+            };
+
+            let line = output_line + i;
+            if self.lines.len() <= line {
+                self.lines.resize(line + 1, None);
+            }
+            if self.lines[line].is_none() {
+                self.lines[line] = Some(origin);
+            }
+        }
+    }
+
+    // Converts a one-based generated line & column into a one-based snippet line & column
+    pub fn lookup(&self, line : usize, col : usize) -> Option<(usize, usize)> {
+        let origin = (*self.lines.get(line.checked_sub(1)?)?)?;
+        let col = col.saturating_sub(1);
+        let input_col = if col >= origin.output_col {
+            col - origin.output_col + origin.input_col
+        } else {
+            origin.input_col
+        };
+        Some((origin.line + 1, input_col + 1))
+    }
+
+    // Rewrites gcc/clang diagnostics so locations refer to the snippet. Diagnostics that point
+    // into code we generated (headers, pretty printers) are dropped along with their excerpts.
+    pub fn translate_diagnostics(&self, diagnostics : &str, file_name : &str) -> String {
+        let location = regex::Regex::new(&format!(r"{}:(\d+):(\d+):", regex::escape(file_name))).unwrap();
+        let excerpt = regex::Regex::new(r"^(\s*)(\d+)( \|.*)$").unwrap();
+        let gutter = regex::Regex::new(r"^\s*\|").unwrap();
+        let foreign = regex::Regex::new(r"^[^\s:]+:\d+:\d+: (\w+):").unwrap();
+
+        let mut output = Vec::new();
+        let mut skipping = false;
+        let mut last_line = None;
+        for line in diagnostics.lines() {
+            if let Some(cap) = location.captures(line) {
+                let out_line = cap[1].parse::<usize>().unwrap_or_default();
+                let out_col = cap[2].parse::<usize>().unwrap_or_default();
+                match self.lookup(out_line, out_col) {
+                    Some((l, c)) => {
+                        skipping = false;
+                        last_line = Some(l);
+                        output.push(location.replace(line, format!("snippet:{}:{}:", l, c).as_str()).to_string());
+                    }
+                    None => {
+                        skipping = true;
+                        last_line = None;
+                    }
+                }
+                continue;
+            }
+
+            // notes from system headers are mostly long lists of overload candidates
+            if let Some(cap) = foreign.captures(line) {
+                skipping = &cap[1] == "note";
+                last_line = None;
+                if !skipping {
+                    output.push(line.to_owned());
+                }
+                continue;
+            }
+
+            // the include chain for our prelude is meaningless to the user
+            if line.starts_with("In file included from") || (skipping && line.trim_start().starts_with("from ")) {
+                skipping = true;
+                continue;
+            }
+
+            // source excerpts & carets belong to the diagnostic above them
+            if let Some(cap) = excerpt.captures(line) {
+                if !skipping {
+                    match last_line {
+                        Some(l) => output.push(format!("{}{}{}", &cap[1], l, &cap[3])),
+                        None => output.push(line.to_owned()),
+                    }
+                }
+                continue;
+            }
+            if gutter.is_match(line) {
+                if !skipping {
+                    output.push(line.to_owned());
+                }
+                continue;
+            }
+
+            skipping = false;
+            output.push(line.replace(file_name, "snippet"));
+        }
+
+        // if everything pointed at generated code the original is better than nothing
+        if output.iter().all(|l| l.trim().is_empty()) {
+            return diagnostics.to_owned();
+        }
+        output.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // generated line & column, and where that should be in the snippet
+    type Case = ((usize, usize), Option<(usize, usize)>);
+
+    #[test]
+    fn multi_line() {
+        let input = "int x;\nint f() {\n  return 1;\n}\n";
+        let offset = input.find("int f").unwrap();
+        let mut map = SourceMap::default();
+        map.add(input, offset, "int f() {\n  return 1;\n}", 3, 2);
+
+        // only the first line is shifted by where we put it
+        let cases : &[Case] = &[
+            ((4, 3), Some((2, 1))),
+            ((4, 7), Some((2, 5))),
+            ((5, 3), Some((3, 3))),
+            ((6, 1), Some((4, 1))),
+            ((3, 1), None),
+            ((7, 1), None),
+            ((0, 1), None),
+        ];
+        for ((line, col), expected) in cases {
+            assert_eq!(map.lookup(*line, *col), *expected, "{}:{}", line, col);
+        }
+    }
+
+    #[test]
+    fn split_line() {
+        // one line of the snippet was split between two places in the program
+        let input = "int x = 1; foo(x);";
+        let mut map = SourceMap::default();
+        map.add(input, 0, "int x = 1;", 2, 0);
+        map.add(input, 11, "foo(x);", 7, 4);
+
+        let cases : &[Case] = &[
+            ((3, 5), Some((1, 5))),
+            ((8, 5), Some((1, 12))),
+            ((8, 7), Some((1, 14))),
+            // indentation we added points at the start of the user's text
+            ((8, 1), Some((1, 12))),
+        ];
+        for ((line, col), expected) in cases {
+            assert_eq!(map.lookup(*line, *col), *expected, "{}:{}", line, col);
+        }
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut map = SourceMap::default();
+        map.add("return x;", 0, "return x;", 9, 4);
+
+        let diagnostics = "\
+prog.cc: In function 'int main()':
+prog.cc:2:1: error: expected ';' before 'junk'
+    2 | junk
+      | ^~~~
+prog.cc:10:12: error: 'x' was not declared in this scope
+   10 |     return x;
+      |            ^";
+        let expected = "\
+snippet: In function 'int main()':
+snippet:1:8: error: 'x' was not declared in this scope
+   1 |     return x;
+      |            ^";
+        assert_eq!(map.translate_diagnostics(diagnostics, "prog.cc"), expected);
+    }
+
+    #[test]
+    fn diagnostics_in_prelude() {
+        let mut map = SourceMap::default();
+        map.add("return x;", 0, "return x;", 9, 4);
+
+        // with nothing left to show we fall back to what the compiler said
+        let diagnostics = "\
+prog.cc:2:1: error: expected ';' before 'junk'
+    2 | junk
+      | ^~~~";
+        assert_eq!(map.translate_diagnostics(diagnostics, "prog.cc"), diagnostics);
+    }
+}
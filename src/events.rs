@@ -1,7 +1,7 @@
 use serenity::{
     async_trait,
     framework::standard:: {
-        macros::hook, CommandResult, DispatchError
+        macros::hook, CommandResult
     },
     model::{
         channel::Message,
@@ -27,26 +27,39 @@ pub struct Handler; // event handler for serenity
 #[async_trait]
 trait ShardsReadyHandler {
     async fn all_shards_ready(&self, ctx: &Context, stats: & mut MutexGuard<'_, StatsManager>, ready : &Ready);
+    async fn guild_count_changed(&self, ctx: &Context, stats: & mut MutexGuard<'_, StatsManager>);
 }
 
 #[async_trait]
 impl ShardsReadyHandler for Handler {
     async fn all_shards_ready(&self, ctx: &Context, stats: & mut MutexGuard<'_, StatsManager>, ready : &Ready) {
-        let data = ctx.data.read().await;
-        let mut info = data.get::<ConfigCache>().unwrap().write().await;
-        info.insert("BOT_AVATAR", ready.user.avatar_url().unwrap());
+        {
+            let data = ctx.data.read().await;
+            let mut config = data.get::<ConfigCache>().unwrap().write().await;
+            config.runtime.avatar = ready.user.avatar_url().unwrap_or_else(|| ready.user.default_avatar_url());
+        }
 
-        let shard_manager = data.get::<ShardManagerCache>().unwrap().lock().await;
-        let guild_count = stats.get_boot_vec_sum();
+        self.guild_count_changed(ctx, stats).await;
+        info!("Ready in {} guilds", stats.server_count());
+    }
+
+    // posts our guild count to the stats api & presence, but only once every shard has reported in
+    async fn guild_count_changed(&self, ctx: &Context, stats: & mut MutexGuard<'_, StatsManager>) {
+        if !stats.is_ready() {
+            return;
+        }
 
-        // update stats
+        let data = ctx.data.read().await;
         if stats.should_track() {
-            stats.post_servers(guild_count).await;
+            stats.post_servers();
         }
 
-        discordhelpers::send_global_presence(&shard_manager, stats.server_count()).await;
+        // bot lists are posted to on a schedule, see apis::botlists
 
-        info!("Ready in {} guilds", stats.server_count());
+        // update guild count in presence
+        let maintenance = data.get::<MaintenanceCache>().unwrap().read().await.status();
+        let shard_manager = data.get::<ShardManagerCache>().unwrap().lock().await;
+        discordhelpers::send_global_presence(&shard_manager, stats.server_count(), maintenance).await;
     }
 }
 
@@ -56,64 +69,45 @@ impl EventHandler for Handler {
         let old_msg = {
             let data = ctx.data.read().await;
             let mut message_cache = data.get::<MessageCache>().unwrap().lock().await;
-            if let Some(msg) = message_cache.get_mut(&new_data.id.0) {
-                Some(msg.clone())
+            if let Some(cached) = message_cache.get_mut(&new_data.id.0) {
+                Some(cached.clone())
             }
             else {
                 None
             }
         };
 
-        if let Some(msg) = old_msg {
+        if let Some(cached) = old_msg {
             if let Some(new_msg) = new_data.content {
                 if let Some (author) = new_data.author {
-                    discordhelpers::handle_edit(&ctx, new_msg, author, msg).await;
+                    discordhelpers::handle_edit(&ctx, new_msg, author, &cached.command, cached.message).await;
                 }
             }
         }
     }
 
     async fn guild_create(&self, ctx: Context, guild: Guild) {
-        let now: DateTime<Utc> = Utc::now();
-        if guild.joined_at + Duration::seconds(30) > now {
+        // we get one of these for every guild when a shard connects, so only guilds we
+        // weren't already tracking are actually new
+        let joined = {
             let data = ctx.data.read().await;
-
-            // post new server to join log
-            let id;
-            {
-                let info = data.get::<ConfigCache>().unwrap().read().await;
-                id = info.get("BOT_ID").unwrap().parse::<u64>().unwrap();
-
-                if let Some(log) = info.get("JOIN_LOG") {
-                    if let Ok(id) = log.parse::<u64>() {
-                        let emb = embeds::build_join_embed(&guild);
-                        discordhelpers::manual_dispatch(ctx.http.clone(), id, emb).await;
-                    }
-                }
-            }
-
-            // publish/queue new server to stats
             let mut stats = data.get::<StatsManagerCache>().unwrap().lock().await;
-            if stats.should_track() {
-                stats.new_server().await;
+            let joined = stats.guild_joined(ctx.shard_id, guild.id.0);
+            if joined {
+                self.guild_count_changed(&ctx, & mut stats).await;
             }
+            joined
+        };
 
-            // ensure we're actually loaded in before we start posting our server counts
-            if stats.server_count() > 0
-            {
-                let new_stats = dbl::types::ShardStats::Cumulative {
-                    server_count: stats.server_count(),
-                    shard_count: Some(stats.shard_count())
-                };
-
-                let dbl = data.get::<DblCache>().unwrap().read().await;
-                if let Err(e) = dbl.update_stats(id, new_stats).await {
-                    warn!("Failed to post stats to dbl: {}", e);
-                }
+        let now: DateTime<Utc> = Utc::now();
+        if joined && guild.joined_at + Duration::seconds(30) > now {
+            let data = ctx.data.read().await;
 
-                // update guild count in presence
-                let shard_manager = data.get::<ShardManagerCache>().unwrap().lock().await;
-                discordhelpers::send_global_presence(&shard_manager, stats.server_count()).await;
+            // post new server to join log
+            let join_log = data.get::<ConfigCache>().unwrap().read().await.logs.join;
+            if let Some(id) = join_log {
+                let emb = embeds::build_join_embed(&guild);
+                discordhelpers::manual_dispatch(ctx.http.clone(), id, emb).await;
             }
 
             info!("Joining {}", guild.name);
@@ -123,8 +117,8 @@ impl EventHandler for Handler {
     async fn message_delete(&self, ctx: Context, _channel_id: ChannelId, id: MessageId, _guild_id: Option<GuildId>) {
         let data = ctx.data.read().await;
         let mut message_cache = data.get::<MessageCache>().unwrap().lock().await;
-        if let Some(msg) = message_cache.get_mut(id.as_u64()) {
-            if msg.delete(ctx.http).await.is_err() {
+        if let Some(cached) = message_cache.get_mut(id.as_u64()) {
+            if cached.message.delete(ctx.http).await.is_err() {
                 // ignore for now
             }
             message_cache.remove(id.as_u64());
@@ -132,40 +126,25 @@ impl EventHandler for Handler {
     }
 
     async fn guild_delete(&self, ctx: Context, incomplete: GuildUnavailable) {
-        let data = ctx.data.read().await;
-
-        // post new server to join log
-        let info = data.get::<ConfigCache>().unwrap().read().await;
-        let id = info.get("BOT_ID").unwrap().parse::<u64>().unwrap();
-        if let Some(log) = info.get("JOIN_LOG") {
-            if let Ok(id) = log.parse::<u64>() {
-                let emb = embeds::build_leave_embed(&incomplete.id);
-                discordhelpers::manual_dispatch(ctx.http.clone(), id, emb).await;
-            }
-        }
-
-        // publish/queue new server to stats
-        let mut stats = data.get::<StatsManagerCache>().unwrap().lock().await;
-        if stats.should_track() {
-            stats.leave_server().await;
+        // an outage isn't a leave, the guild will come back with a guild_create
+        if incomplete.unavailable {
+            return;
         }
 
-        // ensure we're actually loaded in before we start posting our server counts
-        if stats.server_count() > 0
+        let data = ctx.data.read().await;
         {
-            let new_stats = dbl::types::ShardStats::Cumulative {
-                server_count: stats.server_count(),
-                shard_count: Some(stats.shard_count())
-            };
-
-            let dbl = data.get::<DblCache>().unwrap().read().await;
-            if let Err(e) = dbl.update_stats(id, new_stats).await {
-                warn!("Failed to post stats to dbl: {}", e);
+            let mut stats = data.get::<StatsManagerCache>().unwrap().lock().await;
+            if !stats.guild_left(incomplete.id.0) {
+                return;
             }
+            self.guild_count_changed(&ctx, & mut stats).await;
+        }
 
-            // update guild count in presence
-            let shard_manager = data.get::<ShardManagerCache>().unwrap().lock().await;
-            discordhelpers::send_global_presence(&shard_manager, stats.server_count()).await;
+        // post new server to join log
+        let join_log = data.get::<ConfigCache>().unwrap().read().await.logs.join;
+        if let Some(id) = join_log {
+            let emb = embeds::build_leave_embed(&incomplete.id);
+            discordhelpers::manual_dispatch(ctx.http.clone(), id, emb).await;
         }
 
         info!("Leaving {}", &incomplete.id);
@@ -177,30 +156,25 @@ impl EventHandler for Handler {
         let data = ctx.data.read().await;
         let mut stats = data.get::<StatsManagerCache>().unwrap().lock().await;
 
-        // occasionally we can have a ready event fire well after execution
-        // this check prevents us from double calling all_shards_ready
-        let total_shards_to_spawn = ready.shard.unwrap()[1];
-        if stats.shard_count()+1 > total_shards_to_spawn {
-            info!("Skipping duplicate ready event...");
-            return;
-        }
-
-        let guild_count = ready.guilds.len() as u64;
-        stats.add_shard(guild_count);
-
-        if stats.shard_count() == total_shards_to_spawn {
+        // reconnects send us a fresh ready, which replaces everything we knew about this shard
+        let total_shards = ready.shard.map(|s| s[1]).unwrap_or(1);
+        let guilds = ready.guilds.iter().map(|g| g.id().0).collect();
+        if stats.shard_ready(ctx.shard_id, total_shards, guilds) {
             self.all_shards_ready(&ctx, & mut stats, &ready).await;
         }
+        else {
+            self.guild_count_changed(&ctx, & mut stats).await;
+        }
     }
 }
 
 #[hook]
-pub async fn before(ctx: &Context, msg : &Message, _: &str) -> bool {
+pub async fn before(ctx: &Context, msg : &Message, command_name: &str) -> bool {
     let data = ctx.data.read().await;
     {
         let stats = data.get::<StatsManagerCache>().unwrap().lock().await;
         if stats.should_track() {
-            stats.post_request().await;
+            stats.post_request();
         }
     }
 
@@ -235,6 +209,52 @@ pub async fn before(ctx: &Context, msg : &Message, _: &str) -> bool {
         }
     }
 
+    // owners can still get us out of maintenance
+    if !crate::commands::admin::is_admin_command(command_name) {
+        let maintenance = data.get::<MaintenanceCache>().unwrap().read().await;
+        if let Some(notice) = maintenance.check(command_name) {
+            let emb = embeds::build_maintenance_embed(&msg.author, notice);
+            let mut emb_msg = embeds::embed_message(emb);
+            let _ = msg.channel_id.send_message(&ctx.http, |_| &mut emb_msg).await;
+            return false;
+        }
+    }
+
+    // nospam limits, recent voters can skip them
+    let limited = {
+        let config = data.get::<ConfigCache>().unwrap().read().await;
+        if config.ratelimit.commands.iter().any(|c| c == command_name) {
+            let votes = data.get::<VoteCache>().unwrap().read().await;
+            if votes.perks.skip_ratelimit && votes.has_perks(msg.author.id.0) {
+                false
+            }
+            else {
+                let mut ratelimiter = data.get::<RateLimitCache>().unwrap().lock().await;
+                ratelimiter.take(msg.author.id.0, &config.ratelimit).is_some()
+            }
+        }
+        else {
+            false
+        }
+    };
+    if limited {
+        data.get::<MetricsCache>().unwrap().rate_limited();
+
+        let emb =
+            embeds::build_fail_embed(&msg.author, "You are sending requests too fast!");
+        let mut emb_msg = embeds::embed_message(emb);
+        let _ = msg.channel_id.send_message(&ctx.http, |_| &mut emb_msg).await;
+        return false;
+    }
+
+    // in flight until our after hook, so shutdowns wait for us to finish
+    if !data.get::<ShutdownCache>().unwrap().begin() {
+        let emb = embeds::build_fail_embed(&msg.author, "The bot is restarting, please try again in a minute.");
+        let mut emb_msg = embeds::embed_message(emb);
+        let _ = msg.channel_id.send_message(&ctx.http, |_| &mut emb_msg).await;
+        return false;
+    }
+
     true
 }
 
@@ -256,29 +276,23 @@ pub async fn after(
             .await
         {
             let mut message_cache = data.get::<MessageCache>().unwrap().lock().await;
-            message_cache.insert(msg.id.0, sent);
+            message_cache.insert(msg.id.0, CachedMessage { command: String::from(command_name), message: sent });
         }
     }
 
 
+    let metrics = data.get::<MetricsCache>().unwrap();
+    metrics.command_executed(command_name);
+
+    if let Some(db) = data.get::<StatsDatabaseCache>() {
+        db.lock().await.command(command_name, msg.author.id.0, msg.guild_id.map(|g| g.0));
+    }
+
     // push command executed to api
     let stats = data.get::<StatsManagerCache>().unwrap().lock().await;
     if stats.should_track() {
-        stats.command_executed(command_name).await;
+        stats.command_executed(command_name);
     }
-}
 
-#[hook]
-pub async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError) {
-    if let DispatchError::Ratelimited(_) = error {
-        let emb =
-            embeds::build_fail_embed(&msg.author, "You are sending requests too fast!");
-        let mut emb_msg = embeds::embed_message(emb);
-        if msg
-            .channel_id
-            .send_message(&ctx.http, |_| &mut emb_msg)
-            .await
-            .is_err()
-        {}
-    }
+    data.get::<ShutdownCache>().unwrap().end();
 }
@@ -7,6 +7,7 @@ mod events;
 mod stats;
 mod utls;
 mod cppeval;
+mod config;
 //mod managers;
 
 use serenity::{
@@ -15,9 +16,10 @@ use serenity::{
     http::Http,
 };
 
-use std::{collections::HashSet, env, error::Error};
+use std::{collections::HashSet, error::Error};
 
 use crate::apis::dbl::BotsListApi;
+use crate::config::Config;
 
 #[macro_use]
 extern crate log;
@@ -27,20 +29,29 @@ extern crate pretty_env_logger;
 use crate::commands::{
     asm::ASM_COMMAND, botinfo::*, compile::*, compilers::*,
     help::*, languages::*, ping::*, block::*, unblock::*,
-    invite::*, cpp::*
+    invite::*, cpp::*, c::*, py::*, js::*, stats::*, vote::*,
+    admin::*, announcements::*
 };
 
 #[group]
-#[commands(botinfo, compile, languages, compilers, ping, help, asm, block, unblock, invite, cpp)]
+#[commands(botinfo, compile, languages, compilers, ping, help, asm, block, unblock, invite, cpp, c, py, js, stats, vote, admin, announcements)]
 struct General;
 
 /** Spawn bot **/
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    dotenv::dotenv().ok();
+    let env_keys = config::apply_dotenv(&HashSet::new());
     pretty_env_logger::init();
 
-    let token = env::var("BOT_TOKEN")?;
+    let mut config = match Config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            error!("{}", e);
+            return Err(Box::new(e));
+        }
+    };
+
+    let token = config.bot.token.clone();
     let http = Http::new_with_token(&token);
     let (owners, bot_id) = match http.get_current_application_info().await {
         Ok(info) => {
@@ -58,9 +69,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
         Err(why) => {
             warn!("Could not access application info: {:?}", why);
-            warn!("Trying environment variable for bot id...");
-            let id = env::var("BOT_ID").expect("Unable to find BOT_ID environment variable");
-            let bot_id = id.parse::<u64>().expect("Invalid bot id");
+            warn!("Trying configured bot id...");
+            let bot_id = match config.bot.id {
+                Some(id) => id,
+                None => return Err(Box::from("Unable to find our bot id, set bot.id (or BOT_ID)")),
+            };
             (HashSet::new(), serenity::model::id::UserId(bot_id))
         },
     };
@@ -74,24 +87,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .join(", ")
     );
 
-    let prefix = env::var("BOT_PREFIX")?;
+    config.runtime.bot_id = bot_id.0;
+    config.runtime.env_keys = env_keys;
+    let prefix = config.bot.prefix.clone();
     let framework = StandardFramework::new()
         .configure(|c| c.owners(owners).prefix(&prefix))
         .before(events::before)
         .after(events::after)
-        .group(&GENERAL_GROUP)
-        .bucket("nospam", |b| b.delay(3).time_span(10).limit(3))
-        .await
-        .on_dispatch_error(events::dispatch_error);
+        .group(&GENERAL_GROUP);
     let mut client = serenity::Client::builder(token)
         .framework(framework)
         .event_handler(events::Handler)
         .intents(GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::GUILD_MESSAGE_REACTIONS)
         .await?;
 
-    cache::fill(client.data.clone(), &prefix, &bot_id, client.shard_manager.clone()).await?;
+    let dbl = BotsListApi::new(&config.webhook, &config.logs, &config.botlists);
+    cache::fill(client.data.clone(), config, client.shard_manager.clone()).await?;
+
+    apis::botlists::spawn_poster(client.data.clone());
+    cache::spawn_config_watcher(client.data.clone());
+    utls::shutdown::spawn_signal_handler(client.data.clone());
 
-    let dbl = BotsListApi::new();
     if dbl.should_spawn() {
         dbl.spawn(client.cache_and_http.http.clone(), client.data.clone());
     }
@@ -99,6 +115,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     if let Err(why) = client.start_autosharded().await {
         error!("Client error: {:?}", why);
     }
+    info!("Shut down");
 
     Ok(())
 }
@@ -0,0 +1,248 @@
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use rusqlite::{params, Connection};
+
+// Our local statistics store. Everything the external stats api gets is recorded here too,
+// along with who asked and where, so ;stats can answer questions on its own. Inserts are
+// handed to a writer thread with its own connection so they never block the executor.
+
+// records waiting for the writer before we start dropping them
+static WRITE_QUEUE_CAPACITY: usize = 4096;
+// how long a connection waits on the other one's lock before giving up
+static BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+static SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS commands (
+    id INTEGER PRIMARY KEY,
+    command TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    guild_id INTEGER,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS compilations (
+    id INTEGER PRIMARY KEY,
+    language TEXT NOT NULL,
+    backend TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    guild_id INTEGER,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS commands_created_at ON commands (created_at);
+CREATE INDEX IF NOT EXISTS compilations_created_at ON compilations (created_at);
+CREATE INDEX IF NOT EXISTS compilations_user_id ON compilations (user_id);
+";
+
+#[derive(Clone, Copy)]
+pub enum StatsPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsPeriod {
+    pub fn parse(input : &str) -> Option<StatsPeriod> {
+        match input.to_lowercase().as_str() {
+            "day" | "daily" | "24h" => Some(StatsPeriod::Day),
+            "week" | "weekly" | "7d" => Some(StatsPeriod::Week),
+            "month" | "monthly" | "30d" => Some(StatsPeriod::Month),
+            _ => None
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            StatsPeriod::Day => 60 * 60 * 24,
+            StatsPeriod::Week => 60 * 60 * 24 * 7,
+            StatsPeriod::Month => 60 * 60 * 24 * 30,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatsPeriod::Day => "day",
+            StatsPeriod::Week => "week",
+            StatsPeriod::Month => "month",
+        }
+    }
+}
+
+pub struct LanguageUsage {
+    pub language: String,
+    pub total: u64,
+    pub failures: u64,
+}
+
+// requests made in a period, and in the period before it
+pub struct Trend {
+    pub current: u64,
+    pub previous: u64,
+}
+
+enum Record {
+    Command { command: String, user: u64, guild: Option<u64>, created_at: i64 },
+    Compilation { language: String, backend: String, fail: bool, user: u64, guild: Option<u64>, created_at: i64 },
+}
+
+impl Record {
+    fn insert(&self, conn : &Connection) {
+        let res = match self {
+            Record::Command { command, user, guild, created_at } => conn.execute(
+                "INSERT INTO commands (command, user_id, guild_id, created_at) VALUES (?1, ?2, ?3, ?4)",
+                params![command, *user as i64, guild.map(|g| g as i64), created_at],
+            ),
+            Record::Compilation { language, backend, fail, user, guild, created_at } => conn.execute(
+                "INSERT INTO compilations (language, backend, success, user_id, guild_id, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![language, backend, !fail, *user as i64, guild.map(|g| g as i64), created_at],
+            ),
+        };
+        if let Err(e) = res {
+            warn!("Unable to record statistics: {}", e);
+        }
+    }
+}
+
+pub struct StatsDatabase {
+    // only used for queries, inserts go through the writer
+    conn: Connection,
+    // both are taken by close_writer
+    writer: Option<mpsc::SyncSender<Record>>,
+    writer_thread: Option<JoinHandle<()>>,
+}
+
+impl StatsDatabase {
+    pub fn open(path : &str) -> rusqlite::Result<StatsDatabase> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(SCHEMA)?;
+
+        let writer_conn = Connection::open(path)?;
+        writer_conn.busy_timeout(BUSY_TIMEOUT)?;
+        let (writer, receiver) = mpsc::sync_channel::<Record>(WRITE_QUEUE_CAPACITY);
+        let writer_thread = std::thread::spawn(move || {
+            // ends once the sender is dropped and everything queued is written
+            for record in receiver {
+                record.insert(&writer_conn);
+            }
+        });
+
+        Ok(StatsDatabase { conn, writer: Some(writer), writer_thread: Some(writer_thread) })
+    }
+
+    // Stops taking new records. The returned thread finishes once it has written the rest.
+    pub fn close_writer(&mut self) -> Option<JoinHandle<()>> {
+        self.writer = None;
+        self.writer_thread.take()
+    }
+
+    pub fn command(&self, command : &str, user : u64, guild : Option<u64>) {
+        self.record(Record::Command {
+            command: String::from(command),
+            user,
+            guild,
+            created_at: now(),
+        });
+    }
+
+    pub fn compilation(&self, language : &str, backend : &str, fail : bool, user : u64, guild : Option<u64>) {
+        self.record(Record::Compilation {
+            language: String::from(language),
+            backend: String::from(backend),
+            fail,
+            user,
+            guild,
+            created_at: now(),
+        });
+    }
+
+    // never waits, if the writer is this far behind we'd rather lose the record
+    fn record(&self, record : Record) {
+        if let Some(writer) = &self.writer {
+            if let Err(e) = writer.try_send(record) {
+                warn!("Dropping statistics record: {}", e);
+            }
+        }
+    }
+
+    pub fn top_languages(&self, period : StatsPeriod, user : Option<u64>, limit : u32) -> rusqlite::Result<Vec<LanguageUsage>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT language, COUNT(*), SUM(CASE WHEN success THEN 0 ELSE 1 END) FROM compilations \
+             WHERE created_at >= ?1 AND (?2 IS NULL OR user_id = ?2) \
+             GROUP BY language ORDER BY COUNT(*) DESC LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![now() - period.seconds(), user.map(|u| u as i64), limit], |row| {
+            Ok(LanguageUsage {
+                language: row.get(0)?,
+                total: row.get::<_, i64>(1)? as u64,
+                failures: row.get::<_, i64>(2)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn top_guilds(&self, period : StatsPeriod, limit : u32) -> rusqlite::Result<Vec<(u64, u64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT guild_id, COUNT(*) FROM commands \
+             WHERE created_at >= ?1 AND guild_id IS NOT NULL \
+             GROUP BY guild_id ORDER BY COUNT(*) DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![now() - period.seconds(), limit], |row| {
+            Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64))
+        })?;
+        rows.collect()
+    }
+
+    // command usage over the given period compared to the one before it
+    pub fn command_trend(&self, period : StatsPeriod, user : Option<u64>) -> rusqlite::Result<Trend> {
+        let now = now();
+        let count = |from : i64, to : i64| -> rusqlite::Result<u64> {
+            self.conn.query_row(
+                "SELECT COUNT(*) FROM commands WHERE created_at >= ?1 AND created_at < ?2 AND (?3 IS NULL OR user_id = ?3)",
+                params![from, to, user.map(|u| u as i64)],
+                |row| row.get::<_, i64>(0),
+            ).map(|c| c as u64)
+        };
+
+        Ok(Trend {
+            current: count(now - period.seconds(), now + 1)?,
+            previous: count(now - period.seconds() * 2, now - period.seconds())?,
+        })
+    }
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn close_writer() {
+        let path = std::env::temp_dir().join(format!("stats-test-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let mut db = StatsDatabase::open(path).unwrap();
+        for _ in 0..100 {
+            db.command("compile", 1, Some(2));
+        }
+        db.compilation("c++", "wandbox", false, 1, Some(2));
+
+        // everything queued before closing is written before the thread ends
+        db.close_writer().unwrap().join().unwrap();
+        assert_eq!(db.command_trend(StatsPeriod::Day, None).unwrap().current, 100);
+        assert_eq!(db.top_languages(StatsPeriod::Day, Some(1), 5).unwrap()[0].total, 1);
+
+        // anything after is dropped
+        db.command("compile", 1, Some(2));
+        assert!(db.close_writer().is_none());
+        assert_eq!(db.command_trend(StatsPeriod::Day, None).unwrap().current, 100);
+
+        drop(db);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+// Everything we expose on /metrics. Counters are bumped as things happen, gauges are
+// filled in from our caches right before each scrape.
+pub struct Metrics {
+    registry: Registry,
+    commands: IntCounterVec,
+    compilations: IntCounterVec,
+    backend_latency: HistogramVec,
+    rate_limits: IntCounter,
+    guilds: IntGauge,
+    shards: IntGauge,
+    cache_entries: IntGaugeVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let commands = IntCounterVec::new(
+            Opts::new("commands_total", "Commands executed"),
+            &["command"],
+        ).unwrap();
+        let compilations = IntCounterVec::new(
+            Opts::new("compilations_total", "Compilation requests by backend, language and outcome"),
+            &["backend", "language", "status"],
+        ).unwrap();
+        let backend_latency = HistogramVec::new(
+            HistogramOpts::new("backend_request_seconds", "Time spent waiting on a compilation backend")
+                .buckets(vec![0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0]),
+            &["backend"],
+        ).unwrap();
+        let rate_limits = IntCounter::new("rate_limit_hits_total", "Commands rejected by our rate limits").unwrap();
+        let guilds = IntGauge::new("guilds", "Guilds we're in").unwrap();
+        let shards = IntGauge::new("shards", "Shards that have connected").unwrap();
+        let cache_entries = IntGaugeVec::new(
+            Opts::new("cache_entries", "Entries held in each of our caches"),
+            &["cache"],
+        ).unwrap();
+
+        let registry = Registry::new_custom(Some(String::from("compiler_bot")), None).unwrap();
+        registry.register(Box::new(commands.clone())).unwrap();
+        registry.register(Box::new(compilations.clone())).unwrap();
+        registry.register(Box::new(backend_latency.clone())).unwrap();
+        registry.register(Box::new(rate_limits.clone())).unwrap();
+        registry.register(Box::new(guilds.clone())).unwrap();
+        registry.register(Box::new(shards.clone())).unwrap();
+        registry.register(Box::new(cache_entries.clone())).unwrap();
+
+        Metrics {
+            registry,
+            commands,
+            compilations,
+            backend_latency,
+            rate_limits,
+            guilds,
+            shards,
+            cache_entries,
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn command_executed(&self, command : &str) {
+        self.commands.with_label_values(&[command]).inc();
+    }
+
+    pub fn compilation(&self, backend : &str, language : &str, fail : bool, elapsed : Duration) {
+        let status = if fail { "failure" } else { "success" };
+        self.compilations.with_label_values(&[backend, language, status]).inc();
+        self.backend_latency.with_label_values(&[backend]).observe(elapsed.as_secs_f64());
+    }
+
+    pub fn rate_limited(&self) {
+        self.rate_limits.inc();
+    }
+
+    pub fn set_guilds(&self, guilds : u64, shards : u64) {
+        self.guilds.set(guilds as i64);
+        self.shards.set(shards as i64);
+    }
+
+    pub fn set_cache_entries(&self, cache : &str, entries : usize) {
+        self.cache_entries.with_label_values(&[cache]).set(entries as i64);
+    }
+
+    // Prometheus' text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            warn!("Unable to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
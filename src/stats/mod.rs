@@ -1,2 +1,5 @@
 pub mod statsmanager;
 pub mod structures;
+pub mod metrics;
+pub mod database;
+pub mod queue;
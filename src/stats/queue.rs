@@ -0,0 +1,396 @@
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::*;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Instant};
+
+use crate::stats::structures::*;
+
+// Events are handed to a background task so a slow or unreachable stats api never holds up
+// a command. The task sends what it has every few seconds, one request per event as the api
+// has no batch endpoint, backs off when requests fail, and writes anything it can't hold in
+// memory to disk until the api comes back.
+
+// events waiting in the channel before we start dropping them
+static QUEUE_CAPACITY: usize = 4096;
+// most events sent per flush, each is its own request
+static EVENTS_PER_FLUSH: usize = 50;
+// events kept in memory while the api is unreachable, the rest are spilled to disk
+static MEMORY_LIMIT: usize = 1000;
+static FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+static MIN_BACKOFF: Duration = Duration::from_secs(5);
+static MAX_BACKOFF: Duration = Duration::from_secs(60 * 10);
+static SPILL_FILE: &str = "stats_queue.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum StatsEvent {
+    Command { command: String },
+    Language { language: String, fail: bool },
+    Servers { amount: u64 },
+    Request,
+}
+
+impl StatsEvent {
+    async fn deliver(&self, client: Arc<reqwest::Client>, url: &str, key: &str) -> Result<(), reqwest::Error> {
+        let response = match self {
+            StatsEvent::Command { command } => {
+                let mut req = CommandRequest::new(command);
+                req.set_key(key);
+                req.send(client, url).await?
+            }
+            StatsEvent::Language { language, fail } => {
+                let mut req = LanguageRequest::new(language, *fail);
+                req.set_key(key);
+                req.send(client, url).await?
+            }
+            StatsEvent::Servers { amount } => {
+                let mut req = LegacyRequest::new(Some(*amount));
+                req.set_key(key);
+                req.send(client, url).await?
+            }
+            StatsEvent::Request => {
+                let mut req = LegacyRequest::new(None);
+                req.set_key(key);
+                req.send(client, url).await?
+            }
+        };
+        response.error_for_status()?;
+        Ok(())
+    }
+}
+
+pub struct StatsQueue {
+    sender: mpsc::Sender<StatsEvent>,
+    worker: JoinHandle<()>,
+}
+
+impl StatsQueue {
+    pub fn spawn(client: Arc<reqwest::Client>, url: String, key: String) -> StatsQueue {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let worker = tokio::spawn(async move {
+            let mut worker = StatsWorker::new(client, url, key);
+            worker.run(receiver).await;
+        });
+        StatsQueue { sender, worker }
+    }
+
+    // lets the worker send what it can and spill the rest, then waits for it to finish
+    pub async fn close(self) {
+        let StatsQueue { sender, worker } = self;
+        drop(sender);
+        let _ = worker.await;
+    }
+
+    // events sent to the worker that it hasn't picked up yet
+    pub fn depth(&self) -> usize {
+        QUEUE_CAPACITY - self.sender.capacity()
+    }
+
+    // never waits, if the worker is this far behind we'd rather lose the event
+    pub fn push(&self, event: StatsEvent) {
+        if let Err(e) = self.sender.try_send(event) {
+            warn!("Dropping stats event: {}", e);
+        }
+    }
+}
+
+struct StatsWorker {
+    client: Arc<reqwest::Client>,
+    url: String,
+    key: String,
+    pending: VecDeque<StatsEvent>,
+    backoff: Duration,
+    next_attempt: Instant,
+    spill_file: String,
+    spilled: bool,
+}
+
+impl StatsWorker {
+    fn new(client: Arc<reqwest::Client>, url: String, key: String) -> StatsWorker {
+        StatsWorker {
+            client,
+            url,
+            key,
+            pending: VecDeque::new(),
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+            spill_file: String::from(SPILL_FILE),
+            spilled: std::path::Path::new(SPILL_FILE).exists(),
+        }
+    }
+
+    async fn run(&mut self, mut receiver: mpsc::Receiver<StatsEvent>) {
+        loop {
+            match timeout(FLUSH_INTERVAL, receiver.recv()).await {
+                Ok(Some(event)) => {
+                    self.enqueue(event);
+                    while self.pending.len() < EVENTS_PER_FLUSH {
+                        match receiver.try_recv() {
+                            Ok(event) => self.enqueue(event),
+                            Err(_) => break,
+                        }
+                    }
+                }
+                Ok(None) => {
+                    // we're shutting down, keep whatever we couldn't send for next time
+                    self.flush().await;
+                    self.spill(self.pending.len());
+                    return;
+                }
+                Err(_) => (), // nothing new, but we may have something to retry
+            }
+
+            if Instant::now() >= self.next_attempt {
+                self.flush().await;
+            }
+
+            if self.pending.len() > MEMORY_LIMIT {
+                self.spill(self.pending.len() - MEMORY_LIMIT);
+            }
+        }
+    }
+
+    fn enqueue(&mut self, event: StatsEvent) {
+        // only the latest server count is worth sending
+        if let StatsEvent::Servers { .. } = event {
+            self.pending.retain(|e| !matches!(e, StatsEvent::Servers { .. }));
+        }
+        self.pending.push_back(event);
+    }
+
+    // for events older than everything pending, a newer server count beats theirs
+    fn enqueue_front(&mut self, event: StatsEvent) {
+        if let StatsEvent::Servers { .. } = event {
+            if self.pending.iter().any(|e| matches!(e, StatsEvent::Servers { .. })) {
+                return;
+            }
+        }
+        self.pending.push_front(event);
+    }
+
+    async fn flush(&mut self) {
+        if self.pending.is_empty() && self.spilled {
+            self.unspill();
+        }
+
+        let mut sent = 0;
+        while sent < EVENTS_PER_FLUSH {
+            let event = match self.pending.front() {
+                Some(e) => e.clone(),
+                None => break,
+            };
+
+            if let Err(e) = event.deliver(self.client.clone(), &self.url, &self.key).await {
+                warn!("Stats request failed, retrying in {}s: {}", self.backoff.as_secs(), e);
+                self.next_attempt = Instant::now() + self.backoff;
+                self.backoff = std::cmp::min(self.backoff * 2, MAX_BACKOFF);
+                return;
+            }
+
+            self.pending.pop_front();
+            sent += 1;
+        }
+
+        self.backoff = MIN_BACKOFF;
+        self.next_attempt = Instant::now();
+    }
+
+    // writes the newest `count` events to our spill file
+    fn spill(&mut self, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        let mut file = match OpenOptions::new().create(true).append(true).open(&self.spill_file) {
+            Ok(f) => f,
+            Err(e) => return warn!("Unable to open {}, dropping {} stats events: {}", self.spill_file, count, e),
+        };
+
+        let events = self.pending.split_off(self.pending.len() - count);
+        for event in events {
+            if let Ok(line) = serde_json::to_string(&event) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+        self.spilled = true;
+        info!("Spilled {} stats events to {}", count, self.spill_file);
+    }
+
+    // loads as many spilled events as we have room for, ahead of anything pending since they're older
+    fn unspill(&mut self) {
+        self.spilled = false;
+        let contents = match std::fs::read_to_string(&self.spill_file) {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        let _ = std::fs::remove_file(&self.spill_file);
+
+        let room = MEMORY_LIMIT.saturating_sub(self.pending.len());
+        let mut lines = contents.lines();
+        let events: Vec<StatsEvent> = lines
+            .by_ref()
+            .take(room)
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+
+        // newest first, so only the latest server count survives
+        let loaded = events.len();
+        for event in events.into_iter().rev() {
+            self.enqueue_front(event);
+        }
+
+        // anything we can't fit goes back to disk
+        let rest: Vec<&str> = lines.collect();
+        if !rest.is_empty() {
+            if let Ok(mut file) = OpenOptions::new().create(true).write(true).truncate(true).open(&self.spill_file) {
+                let _ = writeln!(file, "{}", rest.join("\n"));
+                self.spilled = true;
+            }
+        }
+        info!("Loaded {} spilled stats events", loaded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use warp::Filter;
+
+    fn command(name: &str) -> StatsEvent {
+        StatsEvent::Command { command: String::from(name) }
+    }
+
+    // a worker whose spill file is kept out of the working directory
+    fn worker(url: &str, name: &str) -> StatsWorker {
+        let mut worker = StatsWorker::new(Arc::new(reqwest::Client::new()), String::from(url), String::from("key"));
+        let path = std::env::temp_dir().join(format!("stats-queue-{}-{}.json", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        worker.spill_file = path.to_str().unwrap().to_owned();
+        worker.spilled = false;
+        worker
+    }
+
+    fn write_spill_file(worker: &mut StatsWorker, events: &[StatsEvent]) {
+        let lines: Vec<String> = events.iter().map(|e| serde_json::to_string(e).unwrap()).collect();
+        std::fs::write(&worker.spill_file, lines.join("\n") + "\n").unwrap();
+        worker.spilled = true;
+    }
+
+    fn pending(worker: &StatsWorker) -> Vec<String> {
+        worker.pending.iter().map(|e| serde_json::to_string(e).unwrap()).collect()
+    }
+
+    fn expected(events: &[StatsEvent]) -> Vec<String> {
+        events.iter().map(|e| serde_json::to_string(e).unwrap()).collect()
+    }
+
+    #[test]
+    fn spill_and_unspill() {
+        let mut worker = worker("http://127.0.0.1:1", "roundtrip");
+        for i in 0..5 {
+            worker.enqueue(command(&i.to_string()));
+        }
+
+        // the newest events go to disk
+        worker.spill(2);
+        assert!(worker.spilled);
+        assert_eq!(pending(&worker), expected(&[command("0"), command("1"), command("2")]));
+
+        worker.pending.clear();
+        worker.enqueue(command("new"));
+        worker.unspill();
+        assert!(!worker.spilled);
+        assert!(!std::path::Path::new(&worker.spill_file).exists());
+        assert_eq!(pending(&worker), expected(&[command("3"), command("4"), command("new")]));
+    }
+
+    #[test]
+    fn unspill_memory_limit() {
+        let mut worker = worker("http://127.0.0.1:1", "limit");
+        for _ in 0..MEMORY_LIMIT - 2 {
+            worker.enqueue(command("pending"));
+        }
+        write_spill_file(&mut worker, &[command("0"), command("1"), command("2"), command("3"), command("4")]);
+
+        worker.unspill();
+        assert_eq!(worker.pending.len(), MEMORY_LIMIT);
+        assert_eq!(pending(&worker)[..2], expected(&[command("0"), command("1")])[..]);
+
+        // the rest stays on disk for later
+        assert!(worker.spilled);
+        worker.pending.clear();
+        worker.unspill();
+        assert_eq!(pending(&worker), expected(&[command("2"), command("3"), command("4")]));
+    }
+
+    #[test]
+    fn unspill_server_counts() {
+        let spilled = [StatsEvent::Servers { amount: 1 }, command("a"), StatsEvent::Servers { amount: 2 }];
+
+        // only the newest spilled count is kept
+        let mut worker = worker("http://127.0.0.1:1", "servers");
+        write_spill_file(&mut worker, &spilled);
+        worker.unspill();
+        assert_eq!(pending(&worker), expected(&[command("a"), StatsEvent::Servers { amount: 2 }]));
+
+        // and none of them if we have a newer one pending
+        worker.pending.clear();
+        worker.enqueue(StatsEvent::Servers { amount: 3 });
+        write_spill_file(&mut worker, &spilled);
+        worker.unspill();
+        assert_eq!(pending(&worker), expected(&[command("a"), StatsEvent::Servers { amount: 3 }]));
+    }
+
+    #[tokio::test]
+    async fn backoff() {
+        // nothing listens on port 1, so every request fails
+        let mut worker = worker("http://127.0.0.1:1", "backoff");
+        worker.enqueue(command("a"));
+
+        worker.flush().await;
+        assert_eq!(worker.pending.len(), 1);
+        assert_eq!(worker.backoff, MIN_BACKOFF * 2);
+        assert!(worker.next_attempt > Instant::now());
+
+        worker.flush().await;
+        assert_eq!(worker.backoff, MIN_BACKOFF * 4);
+
+        worker.backoff = MAX_BACKOFF;
+        worker.flush().await;
+        assert_eq!(worker.backoff, MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn flush() {
+        let received = Arc::new(AtomicUsize::new(0));
+        let counter = received.clone();
+        let api = warp::post().map(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            warp::reply()
+        });
+        let (address, server) = warp::serve(api).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let mut worker = worker(&format!("http://{}", address), "flush");
+        worker.backoff = MIN_BACKOFF * 4;
+        for i in 0..EVENTS_PER_FLUSH + 10 {
+            worker.enqueue(command(&i.to_string()));
+        }
+
+        // one request per event, up to our limit, and a success resets the backoff
+        worker.flush().await;
+        assert_eq!(received.load(Ordering::SeqCst), EVENTS_PER_FLUSH);
+        assert_eq!(worker.pending.len(), 10);
+        assert_eq!(worker.backoff, MIN_BACKOFF);
+
+        worker.flush().await;
+        assert_eq!(received.load(Ordering::SeqCst), EVENTS_PER_FLUSH + 10);
+        assert!(worker.pending.is_empty());
+    }
+}
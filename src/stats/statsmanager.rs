@@ -1,117 +1,110 @@
 use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
 
-use std::env;
+use std::time::{Duration, Instant};
 
-use crate::stats::structures::*;
+use crate::config::StatsConfig;
+use crate::stats::queue::{StatsQueue, StatsEvent};
 
 pub struct StatsManager {
-    client: Arc<reqwest::Client>,
-    url: String,
-    pass: String,
-    servers: u64,
-    shards: u64,
-    boot_count: Vec<u64>,
-    leave_queue: u64,
-    join_queue: u64
+    queue: Option<StatsQueue>,
+    // guild ids we're in, keyed by the shard they belong to
+    shards: HashMap<u64, HashSet<u64>>,
+    total_shards: u64,
+    ready: bool,
+    started: Instant
 }
 
 impl StatsManager {
-    pub fn new() -> StatsManager {
+    pub fn new(config: &StatsConfig) -> StatsManager {
+        // requests are delivered in the background, see stats::queue
+        let mut queue = None;
+        if let (Some(url), Some(pass)) = (&config.api_link, &config.api_key) {
+            queue = Some(StatsQueue::spawn(Arc::new(reqwest::Client::new()), url.clone(), pass.clone()));
+        }
+
         StatsManager {
-            client: Arc::new(reqwest::Client::new()),
-            url: env::var("STATS_API_LINK").unwrap_or_default(),
-            pass: env::var("STATS_API_KEY").unwrap_or_default(),
-            servers: 0,
-            leave_queue: 0,
-            join_queue: 0,
-            shards: 0,
-            boot_count: Vec::new()
+            queue,
+            shards: HashMap::new(),
+            total_shards: 0,
+            ready: false,
+            started: Instant::now()
         }
     }
 
     pub fn should_track(&self) -> bool {
-        !self.url.is_empty() && !self.pass.is_empty()
+        self.queue.is_some()
     }
 
-    pub async fn compilation(&self, language: &str, fail: bool) {
-        let mut cmd = LanguageRequest::new(language, fail);
-        self.send_request::<LanguageRequest>(&mut cmd).await;
+    pub fn compilation(&self, language: &str, fail: bool) {
+        self.send_request(StatsEvent::Language { language: String::from(language), fail });
     }
 
-    pub async fn command_executed(&self, command: &str) {
-        let mut cmd = CommandRequest::new(command);
-        self.send_request::<CommandRequest>(&mut cmd).await;
+    pub fn command_executed(&self, command: &str) {
+        self.send_request(StatsEvent::Command { command: String::from(command) });
     }
 
-    pub async fn post_servers(&mut self, amount: u64) {
-        self.servers = amount;
-
-        // in the connect phase it's entirely possible for our server count to be
-        // zero while we receive a guild left or guild joined event, since they were
-        // queued we can now modify the server count safely
+    // A shard connected or reconnected, its guild list is the source of truth for that shard.
+    // Returns true the first time every shard has reported in.
+    pub fn shard_ready(&mut self, shard: u64, total_shards: u64, guilds: Vec<u64>) -> bool {
+        self.total_shards = total_shards;
+        self.shards.insert(shard, guilds.into_iter().collect());
 
-        // join queue
-        self.servers += self.join_queue;
-        self.join_queue = 0;
-
-        // leave queue
-        self.servers -= self.leave_queue;
-        self.leave_queue = 0;
-
-        // update our stats
-        let mut legacy = LegacyRequest::new(Some(self.servers));
-        self.send_request::<LegacyRequest>(&mut legacy).await;
+        if !self.ready && self.shards.len() as u64 >= total_shards {
+            self.ready = true;
+            return true;
+        }
+        false
     }
 
-    pub async fn new_server(&mut self) {
-        if self.servers < 1 { // not all shards have loaded in yet - queue the join for post_servers
-            self.join_queue += 1;
-            return
-        }
+    // Returns true if we weren't already in this guild
+    pub fn guild_joined(&mut self, shard: u64, guild: u64) -> bool {
+        self.shards.entry(shard).or_insert_with(HashSet::new).insert(guild)
+    }
 
-        self.servers += 1;
-        let mut legacy = LegacyRequest::new(Some(self.servers));
-        self.send_request::<LegacyRequest>(&mut legacy).await;
+    // Returns true if we were in this guild
+    pub fn guild_left(&mut self, guild: u64) -> bool {
+        self.shards.values_mut().any(|guilds| guilds.remove(&guild))
     }
 
-    pub async fn leave_server(&mut self) {
-        if self.servers < 1 { // not loaded in - queue leave for post_servers
-            self.leave_queue += 1;
-            return
-        }
+    pub fn post_servers(&self) {
+        self.send_request(StatsEvent::Servers { amount: self.server_count() });
+    }
 
-        self.servers -= 1;
-        let mut legacy = LegacyRequest::new(Some(self.servers));
-        self.send_request::<LegacyRequest>(&mut legacy).await;
+    pub fn post_request(&self) {
+        self.send_request(StatsEvent::Request);
     }
 
-    pub async fn post_request(&self) {
-        let mut legacy = LegacyRequest::new(None);
-        self.send_request::<LegacyRequest>(&mut legacy).await;
+    // every shard has connected at least once, until then our counts are incomplete
+    pub fn is_ready(&self) -> bool {
+        self.ready
     }
 
     pub fn server_count(&self) -> u64 {
-        self.servers
+        self.shards.values().map(|guilds| guilds.len() as u64).sum()
     }
 
     pub fn shard_count(&self) -> u64 {
-        self.shards
+        self.total_shards
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
     }
 
-    pub fn add_shard(& mut self, server_count : u64) {
-        self.shards += 1;
-        self.boot_count.push(server_count);
+    // None if we aren't sending to a stats api
+    pub fn queue_depth(&self) -> Option<usize> {
+        self.queue.as_ref().map(|q| q.depth())
     }
 
-    pub fn get_boot_vec_sum(&self) -> u64 {
-        self.boot_count.iter().sum()
+    // hands over our queue so it can be closed on shutdown, we stop tracking after this
+    pub fn take_queue(&mut self) -> Option<StatsQueue> {
+        self.queue.take()
     }
 
-    async fn send_request<T: Sendable + std::marker::Sync>(&self, sendable: &mut T) {
-        sendable.set_key(&self.pass);
-        match sendable.send(self.client.clone(), &self.url).await {
-            Ok(_) => (),
-            Err(e) => warn!("Request failed to {}: {}", sendable.endpoint(), e),
+    fn send_request(&self, event: StatsEvent) {
+        if let Some(queue) = &self.queue {
+            queue.push(event);
         }
     }
 }
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::fs;
+
+use serde::*;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Alias {
+    pub language: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+impl Alias {
+    fn new(language : &str, options : &[&str]) -> Alias {
+        Alias {
+            language: String::from(language),
+            options: options.iter().map(|o| String::from(*o)).collect(),
+        }
+    }
+}
+
+/// Maps user-friendly language names to ones our backends understand, loaded from aliases.json
+///
+/// Guild overrides are keyed by guild id and take priority over the global table.
+#[derive(Serialize, Deserialize, Default)]
+pub struct AliasTable {
+    aliases : HashMap<String, Alias>,
+    #[serde(default)]
+    guilds : HashMap<String, HashMap<String, Alias>>,
+}
+
+impl AliasTable {
+    pub fn load() -> Result<AliasTable, String> {
+        let path = std::path::Path::new("aliases.json");
+        if !path.exists() {
+            return Ok(AliasTable::create_aliases());
+        }
+
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read aliases.json: {}", e))?;
+
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Unable to parse aliases.json: {}", e))
+    }
+
+    pub fn resolve(&self, name : &str, guild : Option<u64>) -> Option<&Alias> {
+        let name = name.trim().to_lowercase();
+        if let Some(guild) = guild {
+            if let Some(overrides) = self.guilds.get(&guild.to_string()) {
+                if let Some(alias) = overrides.get(&name) {
+                    return Some(alias);
+                }
+            }
+        }
+        self.aliases.get(&name)
+    }
+
+    // Resolves the language for a name, leaving it untouched if it is not an alias
+    pub fn qualify(&self, name : &str, guild : Option<u64>) -> String {
+        match self.resolve(name, guild) {
+            Some(alias) => alias.language.clone(),
+            None => name.to_owned()
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.aliases.len() + self.guilds.values().map(|g| g.len()).sum::<usize>()
+    }
+
+    pub fn write(&self) {
+        let json = serde_json::to_string_pretty(self)
+            .expect("Unable to serialize aliases.json");
+
+        fs::write("aliases.json", json)
+            .expect("Unable to create aliases.json!");
+    }
+
+    fn create_aliases() -> AliasTable {
+        let mut aliases = HashMap::new();
+
+        // Replace cpp with c++ since we removed the c pre-processor
+        // support for wandbox. This is okay for godbolt requests, too.
+        for name in &["cpp", "cxx", "cc", "hpp"] {
+            aliases.insert(String::from(*name), Alias::new("c++", &[]));
+        }
+        for std in &["c++11", "c++14", "c++17", "c++20", "c++2a", "c++23", "c++2b"] {
+            aliases.insert(String::from(*std), Alias::new("c++", &[format!("-std={}", std).as_str()]));
+        }
+        for std in &["c89", "c99", "c11", "c17"] {
+            aliases.insert(String::from(*std), Alias::new("c", &[format!("-std={}", std).as_str()]));
+        }
+
+        let simple = [
+            ("h", "c"),
+            ("rs", "rust"),
+            ("js", "javascript"),
+            ("node", "javascript"),
+            ("nodejs", "javascript"),
+            ("ts", "typescript"),
+            ("kt", "kotlin"),
+            ("hs", "haskell"),
+            ("golang", "go"),
+            ("py", "python"),
+            ("py3", "python"),
+            ("python3", "python"),
+            ("csharp", "c#"),
+            ("cs", "c#"),
+            ("fsharp", "f#"),
+            ("fs", "f#"),
+            ("rb", "ruby"),
+            ("pl", "perl"),
+            ("sh", "bash"),
+            ("ml", "ocaml"),
+            ("ex", "elixir"),
+            ("exs", "elixir"),
+            ("erl", "erlang"),
+            ("clj", "clojure"),
+            ("scm", "scheme"),
+            ("jl", "julia"),
+            ("cr", "crystal"),
+            ("pas", "pascal"),
+        ];
+        for (name, language) in simple.iter() {
+            aliases.insert(String::from(*name), Alias::new(language, &[]));
+        }
+
+        let table = AliasTable {
+            aliases,
+            guilds: Default::default(),
+        };
+        table.write();
+        table
+    }
+}
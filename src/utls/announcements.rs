@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::fs;
+
+use serde::*;
+
+/// Channels that asked to receive our announcements, keyed by guild
+#[derive(Serialize, Deserialize, Default)]
+pub struct Announcements {
+    channels : HashMap<String, u64>
+}
+
+impl Announcements {
+    pub fn new() -> Announcements {
+        let path = std::path::Path::new("announcements.json");
+        if !path.exists() {
+            return Announcements::create_announcements();
+        }
+
+        let json = fs::read_to_string(path)
+            .expect("Unable to read announcements.json");
+
+        let announcements : Announcements = serde_json::from_str(&json)
+            .expect("Unable to deserialize announcements.json");
+        announcements
+    }
+
+    // a guild only gets one announcement channel, subscribing again moves it
+    pub fn subscribe(&mut self, guild : u64, channel : u64) {
+        self.channels.insert(guild.to_string(), channel);
+        self.write();
+    }
+
+    // returns false if the guild wasn't subscribed
+    pub fn unsubscribe(&mut self, guild : u64) -> bool {
+        let removed = self.channels.remove(&guild.to_string()).is_some();
+        if removed {
+            self.write();
+        }
+        removed
+    }
+
+    pub fn channels(&self) -> Vec<u64> {
+        self.channels.values().copied().collect()
+    }
+
+    pub fn write(&self) {
+        let json = serde_json::to_string(self)
+            .expect("Unable to serialize announcements.json");
+
+        fs::write("announcements.json", json)
+            .expect("Unable to create announcements.json!");
+    }
+
+    fn create_announcements() -> Announcements {
+        let announcements = Announcements {
+            channels : Default::default()
+        };
+        announcements.write();
+        announcements
+    }
+}
@@ -8,18 +8,17 @@ pub struct Blocklist {
 }
 
 impl Blocklist {
-    pub fn new() -> Blocklist {
+    pub fn load() -> Result<Blocklist, String> {
         let path = std::path::Path::new("blocklist.json");
         if !path.exists() {
-            return Blocklist::create_blocklist();
+            return Ok(Blocklist::create_blocklist());
         }
 
         let json = fs::read_to_string(path)
-            .expect("Unable to read blocklist.json");
+            .map_err(|e| format!("Unable to read blocklist.json: {}", e))?;
 
-        let list : Blocklist = serde_json::from_str(&json)
-            .expect("Unable to deserialize blocklist.json");
-        list
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Unable to parse blocklist.json: {}", e))
     }
 
     pub fn contains(&self, snowflake : u64) -> bool {
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::watch;
+
+use crate::config::ConcurrencyConfig;
+
+// Limits how many compilations we run at once, both overall and per backend. Requests past
+// those limits wait in a queue that takes turns between users, so one user sending a burst
+// of requests only holds up their own.
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    Wandbox,
+    Godbolt,
+}
+
+/// The queue was full, the request was never queued
+pub struct QueueFull;
+
+struct Waiter {
+    id : u64,
+    backend : Backend,
+    // how many requests this user had ahead of this one when it was queued
+    round : usize,
+    // 0 once we're running, otherwise our place in line
+    position : watch::Sender<usize>,
+}
+
+struct QueueState {
+    limits : ConcurrencyConfig,
+    next_id : u64,
+    running : usize,
+    running_backend : HashMap<Backend, usize>,
+    // requests each user has running or waiting
+    user_requests : HashMap<u64, usize>,
+    waiting : Vec<Waiter>,
+}
+
+impl QueueState {
+    fn limit(&self, backend : Backend) -> usize {
+        match backend {
+            Backend::Wandbox => self.limits.wandbox,
+            Backend::Godbolt => self.limits.godbolt,
+        }
+    }
+
+    // starts whatever fits under our limits, in queue order, then tells everyone else where they stand
+    fn dispatch(&mut self) {
+        let mut i = 0;
+        while i < self.waiting.len() && self.running < self.limits.global {
+            let backend = self.waiting[i].backend;
+            if *self.running_backend.get(&backend).unwrap_or(&0) < self.limit(backend) {
+                let waiter = self.waiting.remove(i);
+                self.running += 1;
+                *self.running_backend.entry(backend).or_insert(0) += 1;
+                let _ = waiter.position.send(0);
+            }
+            else {
+                i += 1;
+            }
+        }
+
+        for (i, waiter) in self.waiting.iter().enumerate() {
+            if *waiter.position.borrow() != i + 1 {
+                let _ = waiter.position.send(i + 1);
+            }
+        }
+    }
+
+    fn leave(&mut self, user : u64) {
+        if let Some(count) = self.user_requests.get_mut(&user) {
+            *count -= 1;
+            if *count == 0 {
+                self.user_requests.remove(&user);
+            }
+        }
+    }
+}
+
+pub struct RequestQueue {
+    // a std mutex, tickets are released from Drop and nothing awaits while holding it
+    state : Mutex<QueueState>,
+}
+
+impl RequestQueue {
+    pub fn new(limits : ConcurrencyConfig) -> RequestQueue {
+        RequestQueue {
+            state: Mutex::new(QueueState {
+                limits,
+                next_id: 0,
+                running: 0,
+                running_backend: HashMap::new(),
+                user_requests: HashMap::new(),
+                waiting: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn set_limits(&self, limits : ConcurrencyConfig) {
+        let mut state = self.state.lock().unwrap();
+        state.limits = limits;
+        state.dispatch();
+    }
+
+    // Returns the number of requests running and waiting
+    pub fn depth(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.running, state.waiting.len())
+    }
+
+    // Takes a place in line, the request may run once the ticket's position reaches 0
+    pub fn enter(self : &Arc<Self>, user : u64, backend : Backend) -> Result<Ticket, QueueFull> {
+        let mut state = self.state.lock().unwrap();
+
+        // a user's second request goes behind everyone's first, and so on
+        let round = *state.user_requests.get(&user).unwrap_or(&0);
+        let index = state.waiting.iter().position(|w| w.round > round).unwrap_or(state.waiting.len());
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let (sender, receiver) = watch::channel(index + 1);
+        state.waiting.insert(index, Waiter { id, backend, round, position: sender });
+        *state.user_requests.entry(user).or_insert(0) += 1;
+        state.dispatch();
+
+        let queued = state.waiting.iter().any(|w| w.id == id);
+        if queued && state.waiting.len() > state.limits.queue_size {
+            state.waiting.retain(|w| w.id != id);
+            state.leave(user);
+            state.dispatch();
+            return Err(QueueFull);
+        }
+
+        Ok(Ticket {
+            queue: self.clone(),
+            id,
+            user,
+            backend,
+            position: receiver,
+        })
+    }
+
+    fn release(&self, ticket : &Ticket) {
+        let mut state = self.state.lock().unwrap();
+        let waiting = state.waiting.len();
+        state.waiting.retain(|w| w.id != ticket.id);
+
+        // if we weren't waiting we were running
+        if state.waiting.len() == waiting {
+            state.running -= 1;
+            if let Some(running) = state.running_backend.get_mut(&ticket.backend) {
+                *running -= 1;
+            }
+        }
+        state.leave(ticket.user);
+        state.dispatch();
+    }
+}
+
+/// A request's place in line, or its slot once it's running. Dropping it gives either up.
+pub struct Ticket {
+    queue : Arc<RequestQueue>,
+    id : u64,
+    user : u64,
+    backend : Backend,
+    position : watch::Receiver<usize>,
+}
+
+impl Ticket {
+    pub fn position(&self) -> usize {
+        *self.position.borrow()
+    }
+
+    // waits for our place in line to change, None if it never will
+    pub async fn changed(&mut self) -> Option<usize> {
+        match self.position.changed().await {
+            Ok(_) => Some(self.position()),
+            Err(_) => None,
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.queue.release(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_queue(global : usize, wandbox : usize, godbolt : usize, queue_size : usize) -> Arc<RequestQueue> {
+        Arc::new(RequestQueue::new(ConcurrencyConfig { global, wandbox, godbolt, queue_size }))
+    }
+
+    fn enter(queue : &Arc<RequestQueue>, user : u64, backend : Backend) -> Ticket {
+        match queue.enter(user, backend) {
+            Ok(ticket) => ticket,
+            Err(QueueFull) => panic!("queue was full for user {}", user),
+        }
+    }
+
+    fn positions(tickets : &[&Ticket]) -> Vec<usize> {
+        tickets.iter().map(|t| t.position()).collect()
+    }
+
+    #[test]
+    fn rounds_between_users() {
+        let queue = new_queue(1, 10, 10, 50);
+        let a = enter(&queue, 1, Backend::Wandbox);
+        let b = enter(&queue, 1, Backend::Wandbox);
+        let c = enter(&queue, 1, Backend::Wandbox);
+        assert_eq!(positions(&[&a, &b, &c]), vec![0, 1, 2]);
+
+        // other users' first requests go ahead of user 1's second and third
+        let d = enter(&queue, 2, Backend::Wandbox);
+        let e = enter(&queue, 3, Backend::Wandbox);
+        assert_eq!(positions(&[&d, &e, &b, &c]), vec![1, 2, 3, 4]);
+
+        // but not ahead of each other, or of a round they've already reached
+        let f = enter(&queue, 2, Backend::Wandbox);
+        assert_eq!(positions(&[&d, &e, &b, &f, &c]), vec![1, 2, 3, 4, 5]);
+
+        drop(a);
+        assert_eq!(positions(&[&d, &e, &b, &f, &c]), vec![0, 1, 2, 3, 4]);
+        assert_eq!(queue.depth(), (1, 4));
+    }
+
+    #[test]
+    fn backend_limits() {
+        let queue = new_queue(3, 1, 1, 50);
+        let w1 = enter(&queue, 1, Backend::Wandbox);
+        let w2 = enter(&queue, 2, Backend::Wandbox);
+        assert_eq!(positions(&[&w1, &w2]), vec![0, 1]);
+
+        // wandbox being full doesn't hold up godbolt requests queued after it
+        let g1 = enter(&queue, 3, Backend::Godbolt);
+        let g2 = enter(&queue, 4, Backend::Godbolt);
+        assert_eq!(positions(&[&w1, &w2, &g1, &g2]), vec![0, 1, 0, 2]);
+        assert_eq!(queue.depth(), (2, 2));
+
+        drop(w1);
+        assert_eq!(positions(&[&w2, &g1, &g2]), vec![0, 0, 1]);
+
+        drop(g1);
+        assert_eq!(positions(&[&w2, &g2]), vec![0, 0]);
+        assert_eq!(queue.depth(), (2, 0));
+    }
+
+    #[test]
+    fn global_limit() {
+        let queue = new_queue(2, 10, 10, 50);
+        let w = enter(&queue, 1, Backend::Wandbox);
+        let g = enter(&queue, 2, Backend::Godbolt);
+        let waiting = enter(&queue, 3, Backend::Godbolt);
+        assert_eq!(positions(&[&w, &g, &waiting]), vec![0, 0, 1]);
+
+        // raising our limits starts whoever fits
+        queue.set_limits(ConcurrencyConfig { global: 3, wandbox: 10, godbolt: 10, queue_size: 50 });
+        assert_eq!(waiting.position(), 0);
+        assert_eq!(queue.depth(), (3, 0));
+    }
+
+    #[test]
+    fn queue_full() {
+        let queue = new_queue(1, 10, 10, 1);
+        let _running = enter(&queue, 1, Backend::Wandbox);
+        let waiting = enter(&queue, 1, Backend::Wandbox);
+        assert!(queue.enter(2, Backend::Wandbox).is_err());
+        assert_eq!(queue.depth(), (1, 1));
+        assert_eq!(waiting.position(), 1);
+
+        // the rejected request doesn't count towards user 2's rounds
+        queue.set_limits(ConcurrencyConfig { global: 1, wandbox: 10, godbolt: 10, queue_size: 2 });
+        let second = enter(&queue, 2, Backend::Wandbox);
+        assert_eq!(positions(&[&second, &waiting]), vec![1, 2]);
+
+        // a request that can start right away is never turned away
+        let empty = new_queue(1, 10, 10, 0);
+        let _running = enter(&empty, 1, Backend::Godbolt);
+        assert!(empty.enter(2, Backend::Godbolt).is_err());
+    }
+
+    #[test]
+    fn release() {
+        let queue = new_queue(1, 10, 10, 50);
+        let a = enter(&queue, 1, Backend::Wandbox);
+        let b = enter(&queue, 2, Backend::Wandbox);
+        let c = enter(&queue, 3, Backend::Wandbox);
+
+        // giving up our place in line doesn't free a slot
+        drop(b);
+        assert_eq!(positions(&[&a, &c]), vec![0, 1]);
+        assert_eq!(queue.depth(), (1, 1));
+
+        // finishing does
+        drop(a);
+        assert_eq!(c.position(), 0);
+        assert_eq!(queue.depth(), (1, 0));
+
+        drop(c);
+        assert_eq!(queue.depth(), (0, 0));
+
+        // and everyone's rounds start over
+        let d = enter(&queue, 1, Backend::Wandbox);
+        let e = enter(&queue, 1, Backend::Wandbox);
+        let f = enter(&queue, 2, Backend::Wandbox);
+        assert_eq!(positions(&[&d, &f, &e]), vec![0, 1, 2]);
+    }
+}
@@ -9,5 +9,6 @@ pub static ICON_INVITE: &str = "https://i.imgur.com/CZFt69d.png";
 pub static COMPILER_EXPLORER_ICON: &str = "https://i.imgur.com/GIgATFr.png";
 pub static MAX_OUTPUT_LEN: usize = 250;
 pub static MAX_ERROR_LEN: usize = 500;
+pub static MAX_SOURCE_LEN: usize = 1000;
 
-pub static URL_ALLOW_LIST : [&str; 4] = ["pastebin.com", "gist.githubusercontent.com", "hastebin.com", "raw.githubusercontent.com"];
\ No newline at end of file
+pub static GODBOLT_HOSTS : [&str; 3] = ["godbolt.org", "www.godbolt.org", "compiler-explorer.com"];
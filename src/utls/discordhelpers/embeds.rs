@@ -10,6 +10,8 @@ use wandbox::*;
 
 use crate::utls::constants::*;
 use crate::utls::{discordhelpers};
+use crate::stats::database::{LanguageUsage, StatsPeriod, Trend};
+use crate::utls::votes::VoteRecord;
 
 pub async fn edit_message_embed(ctx : &Context, old : & mut Message, emb : CreateEmbed) {
     let _ = old.edit(ctx, |m| {
@@ -21,7 +23,7 @@ pub async fn edit_message_embed(ctx : &Context, old : & mut Message, emb : Creat
     }).await;
 }
 
-pub fn build_compilation_embed(author: &User, res: & mut CompilationResult) -> CreateEmbed {
+pub fn build_compilation_embed(author: &User, res: & mut CompilationResult, max_output: usize) -> CreateEmbed {
     let mut embed = CreateEmbed::default();
 
     if !res.status.is_empty() {
@@ -51,7 +53,7 @@ pub fn build_compilation_embed(author: &User, res: & mut CompilationResult) -> C
         embed.field("Compiler Output", format!("```{}\n```", str), false);
     }
     if !res.program_all.is_empty() {
-        let str = discordhelpers::conform_external_str(&res.program_all, MAX_OUTPUT_LEN);
+        let str = discordhelpers::conform_external_str(&res.program_all, max_output);
         embed.field("Program Output", format!("```\n{}\n```", str), false);
     }
     if !res.url.is_empty() {
@@ -68,7 +70,7 @@ pub fn build_compilation_embed(author: &User, res: & mut CompilationResult) -> C
     embed
 }
 
-pub fn build_asm_embed(author: &User, res: &godbolt::CompilationResult) -> CreateEmbed {
+pub fn build_asm_embed(author: &User, res: &godbolt::CompilationResult, url: Option<&str>) -> CreateEmbed {
     let mut embed = CreateEmbed::default();
 
     if res.code == 0 {
@@ -89,6 +91,9 @@ pub fn build_asm_embed(author: &User, res: &godbolt::CompilationResult) -> Creat
             format!("```\n{}```", compliant_str),
             false,
         );
+        if let Some(url) = url {
+            embed.field("URL", url, false);
+        }
         return embed;
     };
 
@@ -121,6 +126,9 @@ pub fn build_asm_embed(author: &User, res: &godbolt::CompilationResult) -> Creat
         }
         embed.field(&title, format!("```x86asm\n{}\n```", &append), false);
     }
+    if let Some(url) = url {
+        embed.field("URL", url, false);
+    }
 
     embed.title("Assembly Results");
     embed.footer(|f| {
@@ -132,7 +140,16 @@ pub fn build_asm_embed(author: &User, res: &godbolt::CompilationResult) -> Creat
     embed
 }
 
-pub fn build_small_compilation_embed(author: &User, res: & mut CompilationResult) -> CreateEmbed {
+// Lets the user know we picked the language for them
+pub fn add_language_guess(embed: &mut CreateEmbed, language: &str) {
+    embed.field(
+        "Language",
+        format!("No language was given, so I guessed `{}`", language),
+        false,
+    );
+}
+
+pub fn build_small_compilation_embed(author: &User, res: & mut CompilationResult, compiler_info: &str, max_output: usize) -> CreateEmbed {
     let mut embed = CreateEmbed::default();
     if res.status != "0" {
         embed.color(COLOR_FAIL);
@@ -145,13 +162,37 @@ pub fn build_small_compilation_embed(author: &User, res: & mut CompilationResult
         embed.field("Compiler Output", format!("```{}\n```", str), false);
     }
     if !res.program_all.is_empty() {
-        let str = discordhelpers::conform_external_str(&res.program_all, MAX_OUTPUT_LEN);
+        let str = discordhelpers::conform_external_str(&res.program_all, max_output);
         embed.description(format!("```\n{}\n```", str));
     }
     embed.footer(|f| {
         f.text(format!(
-            "Requested by: {} | Powered by wandbox.org",
-            author.tag()
+            "Requested by: {} | {} | Powered by wandbox.org",
+            author.tag(),
+            compiler_info
+        ))
+    });
+
+    embed
+}
+
+// Shows the program generated for a quick-eval request, highlighted as the given language
+pub fn add_generated_source(embed: &mut CreateEmbed, source: &str, highlight: &str) {
+    let str = discordhelpers::conform_external_str(source, MAX_SOURCE_LEN);
+    embed.field("Generated Source", format!("```{}\n{}\n```", highlight, str), false);
+}
+
+pub fn build_source_embed(author: &User, source: &str, highlight: &str, compiler_info: &str) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.color(COLOR_OKAY);
+    embed.title("Generated Source");
+    let str = discordhelpers::conform_external_str(source, MAX_SOURCE_LEN);
+    embed.description(format!("```{}\n{}\n```", highlight, str));
+    embed.footer(|f| {
+        f.text(format!(
+            "Requested by: {} | {} | Not compiled",
+            author.tag(),
+            compiler_info
         ))
     });
 
@@ -167,11 +208,43 @@ pub fn embed_message(emb: CreateEmbed) -> CreateMessage<'static> {
     msg
 }
 
-pub fn build_dblvote_embed(tag: String) -> CreateEmbed {
+pub fn build_dblvote_embed(tag: String, site: &str) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.color(COLOR_OKAY);
+    embed.description(format!("{} voted for us on {}!", tag, site));
+    embed.thumbnail(ICON_VOTE);
+    embed
+}
+
+pub fn build_vote_embed(author : &User, vote_link : &str, record : Option<&VoteRecord>, perks_remaining : i64, perks : &str) -> CreateEmbed {
     let mut embed = CreateEmbed::default();
+    embed.title("Vote for us!");
     embed.color(COLOR_OKAY);
-    embed.description(format!("{} voted for us on top.gg!", tag));
     embed.thumbnail(ICON_VOTE);
+    embed.description(format!("Voting helps more people find the bot.\n\n[Vote here!]({})", vote_link));
+
+    match record {
+        Some(record) => {
+            embed.field("Last vote", chrono::NaiveDateTime::from_timestamp(record.last_vote, 0).format("%Y-%m-%d %H:%M UTC"), true);
+            embed.field("Streak", record.streak, true);
+            embed.field("Total votes", record.total, true);
+        }
+        None => {
+            embed.field("Last vote", "You haven't voted yet", false);
+        }
+    }
+
+    if !perks.is_empty() {
+        if perks_remaining > 0 {
+            let hours = perks_remaining / 3600;
+            let minutes = (perks_remaining % 3600) / 60;
+            embed.field(format!("Perks (active for {}h {}m)", hours, minutes), perks, false);
+        } else {
+            embed.field("Perks for voting", perks, false);
+        }
+    }
+
+    embed.footer(|f| f.text(format!("Requested by: {}", author.tag())));
     embed
 }
 
@@ -235,6 +308,138 @@ pub fn build_complog_embed(
     embed
 }
 
+pub fn build_stats_embed(author: &User, title: &str, period: StatsPeriod, languages: &[LanguageUsage], trend: &Trend) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.color(COLOR_OKAY);
+    embed.title(title);
+
+    let change = if trend.previous == 0 {
+        String::from("no data for the period before")
+    } else {
+        let percent = (trend.current as f64 - trend.previous as f64) / trend.previous as f64 * 100.0;
+        format!("{:+.0}% from the {} before", percent, period.name())
+    };
+    embed.field(
+        format!("Commands this {}", period.name()),
+        format!("{} ({})", trend.current, change),
+        false,
+    );
+
+    if languages.is_empty() {
+        embed.description("No compilations have been recorded yet.");
+    } else {
+        let mut list = String::new();
+        for (i, usage) in languages.iter().enumerate() {
+            let failed = usage.failures as f64 / usage.total as f64 * 100.0;
+            list.push_str(&format!("{}. `{}` - {} requests, {:.0}% failed\n", i + 1, usage.language, usage.total, failed));
+        }
+        embed.field(format!("Languages this {}", period.name()), list, false);
+    }
+
+    embed.footer(|f| f.text(format!("Requested by: {}", author.tag())));
+    embed
+}
+
+pub fn build_guild_stats_embed(author: &User, period: StatsPeriod, guilds: &[(u64, u64)]) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.color(COLOR_OKAY);
+    embed.title(format!("Busiest guilds this {}", period.name()));
+
+    if guilds.is_empty() {
+        embed.description("No commands have been recorded yet.");
+    } else {
+        let mut list = String::new();
+        for (i, (guild, count)) in guilds.iter().enumerate() {
+            list.push_str(&format!("{}. `{}` - {} commands\n", i + 1, guild, count));
+        }
+        embed.description(list);
+    }
+
+    embed.footer(|f| f.text(format!("Requested by: {}", author.tag())));
+    embed
+}
+
+pub struct StatusInfo {
+    pub uptime: std::time::Duration,
+    pub guilds: u64,
+    // shard id, latency and connection stage
+    pub shards: Vec<(u64, Option<std::time::Duration>, String)>,
+    pub caches: Vec<(&'static str, usize)>,
+    pub stats_queue: Option<usize>,
+    // compilations running and waiting for a slot
+    pub requests: (usize, usize),
+    pub maintenance: Option<String>,
+}
+
+pub fn build_status_embed(author: &User, status: &StatusInfo) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.color(if status.maintenance.is_some() { COLOR_FAIL } else { COLOR_OKAY });
+    embed.title("Runtime status");
+
+    let secs = status.uptime.as_secs();
+    embed.field("Uptime", format!("{}d {}h {}m", secs / 86400, (secs % 86400) / 3600, (secs % 3600) / 60), true);
+    embed.field("Guilds", status.guilds, true);
+    embed.field("Maintenance", status.maintenance.as_deref().unwrap_or("Off"), true);
+
+    let mut shards = String::new();
+    for (id, latency, stage) in &status.shards {
+        let latency = match latency {
+            Some(l) => format!("{}ms", l.as_millis()),
+            None => String::from("?"),
+        };
+        shards.push_str(&format!("`{}` - {} ({})\n", id, latency, stage));
+    }
+    if shards.is_empty() {
+        shards.push_str("No shards running");
+    }
+    embed.field("Shards", shards, false);
+
+    let mut caches = String::new();
+    for (name, size) in &status.caches {
+        caches.push_str(&format!("`{}` - {}\n", name, size));
+    }
+    embed.field("Caches", caches, true);
+
+    let queue = match status.stats_queue {
+        Some(depth) => format!("{} pending", depth),
+        None => String::from("Disabled"),
+    };
+    embed.field("Stats queue", queue, true);
+    embed.field("Requests", format!("{} running\n{} queued", status.requests.0, status.requests.1), true);
+
+    embed.footer(|f| f.text(format!("Requested by: {}", author.tag())));
+    embed
+}
+
+pub fn build_maintenance_embed(author: &User, notice: &str) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.color(COLOR_FAIL);
+    embed.title("Maintenance");
+    embed.description(notice);
+    embed.thumbnail(ICON_FAIL);
+    embed.footer(|f| f.text(format!("Requested by: {}", author.tag())));
+    embed
+}
+
+pub fn build_audit_embed(author: &User, action: &str) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.color(COLOR_OKAY);
+    embed.title("Owner command");
+    embed.description(action);
+    embed.field("Owner", author.tag(), true);
+    embed.field("Owner ID", author.id.0, true);
+    embed
+}
+
+pub fn build_announcement_embed(announcement: &str) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.color(COLOR_OKAY);
+    embed.title("Announcement");
+    embed.description(announcement);
+    embed.footer(|f| f.text("Use ;announcements off to stop receiving these"));
+    embed
+}
+
 pub fn build_fail_embed(author: &User, err: &str) -> CreateEmbed {
     let mut embed = CreateEmbed::default();
     embed.color(COLOR_FAIL);
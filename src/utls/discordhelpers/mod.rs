@@ -12,12 +12,20 @@ use serenity::{
 use serenity_utils::menu::*;
 
 use crate::utls::constants::*;
-use crate::utls::{discordhelpers};
+use crate::utls::{discordhelpers, parser};
 use tokio::sync::{MutexGuard};
 use serenity::client::bridge::gateway::{ShardManager};
-use crate::cache::{ConfigCache};
+use crate::cache::{ConfigCache, EditHandlerCache, MaintenanceCache, ShutdownCache, RequestQueueCache, MessageCache, CachedMessage};
+use crate::utls::concurrency::{Backend, Ticket};
+use crate::cppeval::quickeval::QuickEval;
 use serenity::client::Context;
-use serenity::framework::standard::CommandResult;
+use serenity::framework::standard::{CommandResult, CommandError};
+use futures_util::future::BoxFuture;
+
+pub type EditFuture<'a> = BoxFuture<'a, CommandResult>;
+
+/// Re-runs a command in place of our old reply, given the edited message content
+pub type EditHandler = for<'a> fn(&'a Context, String, User, Message) -> EditFuture<'a>;
 
 pub fn build_menu_items(
     items: Vec<String>,
@@ -103,32 +111,46 @@ pub fn build_reaction(emoji_id: u64, emoji_name: &str) -> ReactionType {
     }
 }
 
-pub async fn handle_edit(ctx : &Context, content : String, author : User, mut old : Message) {
-    let prefix = {
+pub async fn handle_edit(ctx : &Context, content : String, author : User, command : &str, mut old : Message) {
+    let (handler, notice, shutdown) = {
         let data = ctx.data.read().await;
-        let info = data.get::<ConfigCache>().unwrap().read().await;
-        info.get("BOT_PREFIX").unwrap().to_owned()
+        let handlers = data.get::<EditHandlerCache>().unwrap();
+        let maintenance = data.get::<MaintenanceCache>().unwrap().read().await;
+        let shutdown = data.get::<ShutdownCache>().unwrap().clone();
+        (handlers.get(command).copied(), maintenance.check(command).map(String::from), shutdown)
     };
 
+    // same as our before & after hooks, shutdowns wait for edits to finish
+    if !shutdown.begin() {
+        let emb = embeds::build_fail_embed(&author, "The bot is restarting, please try again in a minute.");
+        embeds::edit_message_embed(&ctx, & mut old, emb).await;
+        return;
+    }
+
+    // edits skip our before hook, so maintenance has to be checked here too
+    if let Some(notice) = notice {
+        let emb = embeds::build_maintenance_embed(&author, &notice);
+        embeds::edit_message_embed(&ctx, & mut old, emb).await;
+        shutdown.end();
+        return;
+    }
+
     // try to clear reactions
     let _ = old.delete_reactions(&ctx).await;
 
-    if content.starts_with(&format!("{}asm", prefix)) {
-        if let Err(e) = handle_edit_asm(&ctx, content, author.clone(), old.clone()).await {
-            let err = embeds::build_fail_embed(&author, &e.to_string());
-            embeds::edit_message_embed(&ctx, & mut old, err).await;
+    match handler {
+        Some(handler) => {
+            if let Err(e) = handler(ctx, content, author.clone(), old.clone()).await {
+                let err = embeds::build_fail_embed(&author, &e.to_string());
+                embeds::edit_message_embed(&ctx, & mut old, err).await;
+            }
         }
-    }
-    else if content.starts_with(&format!("{}compile", prefix)) {
-        if let Err(e) = handle_edit_compile(&ctx, content, author.clone(), old.clone()).await {
-            let err = embeds::build_fail_embed(&author, &e.to_string());
+        None => {
+            let err = embeds::build_fail_embed(&author, "Invalid command for edit functionality!");
             embeds::edit_message_embed(&ctx, & mut old, err).await;
         }
     }
-    else {
-        let err = embeds::build_fail_embed(&author, "Invalid command for edit functionality!");
-        embeds::edit_message_embed(&ctx, & mut old, err).await;
-    }
+    shutdown.end();
 }
 
 pub async fn handle_edit_compile(ctx : &Context, content : String, author : User, mut old : Message) -> CommandResult {
@@ -151,24 +173,49 @@ pub async fn handle_edit_asm(ctx : &Context, content : String, author : User, mu
     Ok(())
 }
 
+pub async fn handle_edit_quick_eval<T: QuickEval + Send>(ctx : &Context, content : String, author : User, mut old : Message, eval : fn(&str) -> T, command : &str) -> CommandResult {
+    let eval = eval(parser::get_snippet(&content, command)?);
+    let embed = crate::apis::wandbox::send_quick_eval(ctx, eval, &author, &old).await?;
+
+    let compilation_successful = embed.0.get("color").unwrap() == COLOR_OKAY;
+    discordhelpers::send_completion_react(ctx, &old, compilation_successful).await?;
+
+    embeds::edit_message_embed(&ctx, & mut old, embed).await;
+    Ok(())
+}
+
+// Shared by ;cpp, ;c, ;py & ;js - runs the snippet following the command and remembers our reply for edits
+pub async fn run_quick_eval<T: QuickEval + Send>(ctx : &Context, msg : &Message, eval : fn(&str) -> T, command : &str) -> CommandResult {
+    let eval = eval(parser::get_snippet(&msg.content, command)?);
+    let emb = crate::apis::wandbox::send_quick_eval(ctx, eval, &msg.author, msg).await?;
+    let mut emb_msg = embeds::embed_message(emb);
+
+    // Dispatch our request
+    let compilation_embed = msg
+        .channel_id
+        .send_message(&ctx.http, |_| &mut emb_msg)
+        .await?;
+
+    // Success/fail react
+    let compilation_successful = compilation_embed.embeds[0].colour.0 == COLOR_OKAY;
+    send_completion_react(ctx, &compilation_embed, compilation_successful).await?;
+
+    let data_read = ctx.data.read().await;
+    let mut delete_cache = data_read.get::<MessageCache>().unwrap().lock().await;
+    delete_cache.insert(msg.id.0, CachedMessage { command: String::from(command), message: compilation_embed });
+    Ok(())
+}
+
 pub async fn send_completion_react(ctx: &Context, msg: &Message, success: bool) -> Result<Reaction, serenity::Error> {
     let reaction;
     if success {
         let success_id;
         let success_name;
         {
-            let botinfo_lock = ctx.data.read().await
-                .get::<ConfigCache>()
-                .expect("Expected ConfigCache in global cache")
-                .clone();
-            let botinfo = botinfo_lock.read().await;
-            success_id = botinfo
-                .get("SUCCESS_EMOJI_ID")
-                .unwrap()
-                .clone()
-                .parse::<u64>()
-                .unwrap();
-            success_name = botinfo.get("SUCCESS_EMOJI_NAME").unwrap().clone();
+            let data_read = ctx.data.read().await;
+            let config = data_read.get::<ConfigCache>().unwrap().read().await;
+            success_id = config.emoji.success_id;
+            success_name = config.emoji.success_name.clone();
         }
 
         reaction = discordhelpers::build_reaction(success_id, &success_name);
@@ -179,6 +226,40 @@ pub async fn send_completion_react(ctx: &Context, msg: &Message, success: bool)
 
 }
 
+// Waits for our turn to send a request to the given backend. The returned ticket holds our
+// slot, so it must be kept until the request is done.
+pub async fn wait_in_queue(ctx: &Context, msg: &Message, author: &User, backend: Backend) -> Result<Ticket, CommandError> {
+    let queue = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<RequestQueueCache>().unwrap().clone()
+    };
+
+    let mut ticket = match queue.enter(author.id.0, backend) {
+        Ok(t) => t,
+        Err(_) => return Err(CommandError::from("We're handling too many requests right now, please try again in a minute."))
+    };
+    if ticket.position() == 0 {
+        return Ok(ticket);
+    }
+
+    // keep the user posted on where they are in line
+    let notice = |position: usize| format!("⏳ {}, you're #{} in the queue", author.mention(), position);
+    let mut queue_msg = msg.channel_id.say(&ctx.http, notice(ticket.position())).await.ok();
+    while let Some(position) = ticket.changed().await {
+        if position == 0 {
+            break;
+        }
+        if let Some(queue_msg) = &mut queue_msg {
+            let _ = queue_msg.edit(&ctx.http, |m| m.content(notice(position))).await;
+        }
+    }
+
+    if let Some(queue_msg) = queue_msg {
+        let _ = queue_msg.delete(&ctx.http).await;
+    }
+    Ok(ticket)
+}
+
 // Certain compiler outputs use unicode control characters that
 // make the user experience look nice (colors, etc). This ruins
 // the look of the compiler messages in discord, so we strip them out
@@ -221,12 +302,31 @@ pub async fn manual_dispatch(http: Arc<Http>, id: u64, emb: CreateEmbed) {
     };
 }
 
-pub async fn send_global_presence(shard_manager : &MutexGuard<'_, ShardManager>, sum : u64) {
-    // update shard guild count & presence
-    let presence_str = format!("in {} servers | ;invite", sum);
+// Records an owner command in our logs, and in our audit log channel if it's set
+pub async fn audit_log(ctx: &Context, author: &User, action: &str) {
+    info!("{} [{}]: {}", author.tag(), author.id.0, action);
+
+    let log = {
+        let data = ctx.data.read().await;
+        let config = data.get::<ConfigCache>().unwrap().read().await;
+        config.logs.audit
+    };
+
+    if let Some(id) = log {
+        let emb = embeds::build_audit_embed(author, action);
+        manual_dispatch(ctx.http.clone(), id, emb).await;
+    }
+}
+
+pub async fn send_global_presence(shard_manager : &MutexGuard<'_, ShardManager>, sum : u64, maintenance : Option<String>) {
+    // update shard guild count & presence, we go idle while anything is under maintenance
+    let (presence_str, status) = match maintenance {
+        Some(m) => (format!("{} | ;help", m), OnlineStatus::Idle),
+        None => (format!("in {} servers | ;invite", sum), OnlineStatus::Online),
+    };
 
     let runners = shard_manager.runners.lock().await;
     for (_, v) in runners.iter() {
-        v.runner_tx.set_presence(Some(Activity::playing(&presence_str)), OnlineStatus::Online);
+        v.runner_tx.set_presence(Some(Activity::playing(&presence_str)), status);
     }
 }
\ No newline at end of file
@@ -0,0 +1,299 @@
+// A small heuristic classifier used to guess the language of a request
+// when the user didn't give us one.
+//
+// We look at (in order of trust) the shebang, the file extension of any
+// attachments, and finally a handful of keywords and tokens that are fairly
+// unique to each language.
+
+// Guesses below this confidence are not acted upon
+pub static DETECTION_THRESHOLD: f32 = 0.6;
+
+// The minimum keyword score a guess needs before we even consider it
+static MIN_KEYWORD_SCORE: u32 = 4;
+
+pub struct LanguageGuess {
+    pub language: &'static str,
+    pub confidence: f32,
+}
+
+impl LanguageGuess {
+    pub fn is_confident(&self) -> bool {
+        self.confidence >= DETECTION_THRESHOLD
+    }
+}
+
+static SHEBANGS: [(&str, &str); 8] = [
+    ("python", "python"),
+    ("node", "javascript"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("lua", "lua"),
+    ("bash", "bash"),
+    ("sh", "bash"),
+];
+
+static EXTENSIONS: [(&str, &str); 22] = [
+    ("cpp", "c++"),
+    ("cc", "c++"),
+    ("cxx", "c++"),
+    ("hpp", "c++"),
+    ("c", "c"),
+    ("h", "c"),
+    ("rs", "rust"),
+    ("py", "python"),
+    ("js", "javascript"),
+    ("ts", "typescript"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("go", "go"),
+    ("cs", "c#"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("hs", "haskell"),
+    ("lua", "lua"),
+    ("swift", "swift"),
+    ("scala", "scala"),
+    ("pl", "perl"),
+    ("sh", "bash"),
+];
+
+struct LanguageMarkers {
+    language: &'static str,
+    markers: &'static [(&'static str, u32)],
+}
+
+static KEYWORDS: [LanguageMarkers; 10] = [
+    LanguageMarkers {
+        language: "c++",
+        markers: &[
+            ("#include <iostream>", 6),
+            ("#include <vector>", 5),
+            ("#include <bits/stdc++.h>", 6),
+            ("std::", 4),
+            ("cout <<", 4),
+            ("cin >>", 4),
+            ("using namespace", 4),
+            ("template <", 3),
+            ("template<", 3),
+            ("nullptr", 2),
+            ("#include", 1),
+            ("int main(", 1),
+        ],
+    },
+    LanguageMarkers {
+        language: "c",
+        markers: &[
+            ("#include <stdio.h>", 5),
+            ("#include <stdlib.h>", 4),
+            ("printf(", 2),
+            ("scanf(", 2),
+            ("malloc(", 2),
+            ("#include", 1),
+            ("int main(", 1),
+        ],
+    },
+    LanguageMarkers {
+        language: "rust",
+        markers: &[
+            ("fn main()", 6),
+            ("println!", 5),
+            ("let mut ", 4),
+            ("impl ", 2),
+            ("&mut ", 2),
+            ("use std::", 4),
+            ("-> ", 1),
+        ],
+    },
+    LanguageMarkers {
+        language: "python",
+        markers: &[
+            ("def ", 3),
+            ("elif ", 4),
+            ("print(", 2),
+            ("import ", 1),
+            ("self.", 2),
+            ("__name__", 5),
+            ("in range(", 4),
+            ("None", 1),
+        ],
+    },
+    LanguageMarkers {
+        language: "javascript",
+        markers: &[
+            ("console.log", 6),
+            ("function ", 2),
+            ("const ", 1),
+            ("=> ", 1),
+            ("require(", 3),
+            ("document.", 3),
+            ("===", 3),
+        ],
+    },
+    LanguageMarkers {
+        language: "java",
+        markers: &[
+            ("public static void main", 8),
+            ("System.out.print", 6),
+            ("public class", 4),
+            ("import java.", 6),
+            ("String[]", 2),
+        ],
+    },
+    LanguageMarkers {
+        language: "go",
+        markers: &[
+            ("package main", 6),
+            ("func main()", 6),
+            ("fmt.", 4),
+            (":= ", 2),
+        ],
+    },
+    LanguageMarkers {
+        language: "c#",
+        markers: &[
+            ("using System", 6),
+            ("Console.Write", 6),
+            ("static void Main", 6),
+            ("namespace ", 2),
+        ],
+    },
+    LanguageMarkers {
+        language: "ruby",
+        markers: &[
+            ("puts ", 3),
+            ("end\n", 2),
+            ("def ", 1),
+            (".each do", 5),
+            ("require '", 3),
+        ],
+    },
+    LanguageMarkers {
+        language: "php",
+        markers: &[
+            ("<?php", 10),
+            ("echo ", 2),
+            ("$", 1),
+        ],
+    },
+];
+
+pub fn detect(code : &str, filenames : &[String]) -> Option<LanguageGuess> {
+    if let Some(language) = detect_shebang(code) {
+        return Some(LanguageGuess { language, confidence: 1.0 });
+    }
+
+    if let Some(language) = detect_extension(filenames) {
+        return Some(LanguageGuess { language, confidence: 0.95 });
+    }
+
+    detect_keywords(code)
+}
+
+fn detect_shebang(code : &str) -> Option<&'static str> {
+    let first_line = code.trim_start().lines().next()?;
+    if !first_line.starts_with("#!") {
+        return None;
+    }
+
+    // both "#!/usr/bin/python3" and "#!/usr/bin/env python3" are common
+    let interpreter = first_line
+        .split(|c : char| c == '/' || c.is_whitespace())
+        .rfind(|part| !part.is_empty() && *part != "env")?;
+
+    SHEBANGS
+        .iter()
+        .find(|(name, _)| interpreter.trim_end_matches(|c : char| c.is_ascii_digit() || c == '.') == *name)
+        .map(|(_, language)| *language)
+}
+
+fn detect_extension(filenames : &[String]) -> Option<&'static str> {
+    for filename in filenames {
+        let extension = match filename.rsplit_once('.') {
+            Some((_, ext)) => ext.to_lowercase(),
+            None => continue,
+        };
+
+        if let Some((_, language)) = EXTENSIONS.iter().find(|(ext, _)| *ext == extension) {
+            return Some(language);
+        }
+    }
+    None
+}
+
+fn detect_keywords(code : &str) -> Option<LanguageGuess> {
+    let mut best : Option<(&'static str, u32)> = None;
+    let mut total = 0;
+    for entry in KEYWORDS.iter() {
+        let score : u32 = entry.markers
+            .iter()
+            .map(|(marker, weight)| code.matches(marker).count().min(3) as u32 * weight)
+            .sum();
+
+        total += score;
+        match best {
+            Some((_, best_score)) if best_score >= score => (),
+            _ => best = Some((entry.language, score)),
+        }
+    }
+
+    let (language, score) = best?;
+    if score < MIN_KEYWORD_SCORE {
+        return None;
+    }
+
+    Some(LanguageGuess {
+        language,
+        confidence: score as f32 / total as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detection() {
+        // code, attachment names, the language we should guess & whether we're confident in it
+        let cases : &[(&str, &[&str], Option<&str>, bool)] = &[
+            ("#!/usr/bin/python3\nprint(1)", &[], Some("python"), true),
+            ("#!/usr/bin/env node\nconsole.log(1)", &[], Some("javascript"), true),
+            ("#!/usr/bin/env python3.11\nprint(1)", &[], Some("python"), true),
+            ("  #!/bin/sh\necho hi", &[], Some("bash"), true),
+            // the shebang wins over the attachment
+            ("#!/usr/bin/env ruby\nputs 1", &["main.py"], Some("ruby"), true),
+            ("", &["notes", "main.RS"], Some("rust"), true),
+            ("#include <iostream>\nint main() {\n    std::cout << \"hi\";\n}", &[], Some("c++"), true),
+            ("#include <stdio.h>\nint main() {\n    printf(\"hi\\n\");\n}", &[], Some("c"), true),
+            // scores between languages that are too close to act on
+            ("function f(self) { print(self.x) }\nconst y = f;", &[], Some("python"), false),
+            // too little to go on at all
+            ("x = 1\nprint(x)", &[], None, false),
+            ("#!/usr/bin/env unknown\nx", &[], None, false),
+        ];
+
+        for (code, filenames, language, confident) in cases {
+            let filenames : Vec<String> = filenames.iter().map(|f| String::from(*f)).collect();
+            let guess = detect(code, &filenames);
+            assert_eq!(guess.as_ref().map(|g| g.language), *language, "{}", code);
+            assert_eq!(guess.map(|g| g.is_confident()).unwrap_or(false), *confident, "{}", code);
+        }
+    }
+
+    #[test]
+    fn keyword_confidence() {
+        // the share of the total score that went to the best language
+        let cases : &[(&str, &str, f32)] = &[
+            ("#include <iostream>\nint main() {\n    std::cout << \"hi\";\n}", "c++", 16.0 / 18.0),
+            ("#include <stdio.h>\nint main() {\n    printf(\"hi\\n\");\n}", "c", 9.0 / 11.0),
+            ("function f(self) { print(self.x) }\nconst y = f;", "python", 4.0 / 7.0),
+        ];
+
+        for (code, language, confidence) in cases {
+            let guess = detect_keywords(code).unwrap();
+            assert_eq!(guess.language, *language, "{}", code);
+            assert!((guess.confidence - confidence).abs() < 0.001, "{}: {}", code, guess.confidence);
+        }
+        assert!(4.0 / 7.0 < DETECTION_THRESHOLD);
+    }
+}
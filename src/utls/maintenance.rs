@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+// Lets owners turn away commands while something is broken, see ;admin maintenance. Maintenance
+// can cover everything, or just the commands that rely on one of our backends.
+
+// these keep working no matter what, they don't need wandbox or godbolt
+static EXEMPT_COMMANDS: [&str; 8] = ["help", "ping", "botinfo", "invite", "vote", "languages", "compilers", "announcements"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaintenanceScope {
+    Global,
+    Wandbox,
+    Godbolt,
+}
+
+impl MaintenanceScope {
+    pub fn parse(input : &str) -> Option<MaintenanceScope> {
+        match input.to_lowercase().as_str() {
+            "global" | "all" => Some(MaintenanceScope::Global),
+            "wandbox" => Some(MaintenanceScope::Wandbox),
+            "godbolt" => Some(MaintenanceScope::Godbolt),
+            _ => None
+        }
+    }
+
+    // the backend a command needs, if any
+    fn for_command(command : &str) -> Option<MaintenanceScope> {
+        match command {
+            "compile" | "cpp" | "c" | "py" | "js" => Some(MaintenanceScope::Wandbox),
+            "asm" => Some(MaintenanceScope::Godbolt),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaintenanceScope::Global => "global",
+            MaintenanceScope::Wandbox => "wandbox",
+            MaintenanceScope::Godbolt => "godbolt",
+        }
+    }
+
+    fn default_notice(&self) -> &'static str {
+        match self {
+            MaintenanceScope::Global => "The bot is currently undergoing maintenance, please try again later.",
+            MaintenanceScope::Wandbox => "Code execution is unavailable while wandbox.org is having issues, please try again later.",
+            MaintenanceScope::Godbolt => "Assembly output is unavailable while godbolt.org is having issues, please try again later.",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Maintenance {
+    // active scopes, along with the notice we were given for them
+    scopes : HashMap<MaintenanceScope, Option<String>>,
+}
+
+impl Maintenance {
+    pub fn enable(&mut self, scope : MaintenanceScope, notice : Option<String>) {
+        self.scopes.insert(scope, notice);
+    }
+
+    // returns false if this scope wasn't under maintenance
+    pub fn disable(&mut self, scope : MaintenanceScope) -> bool {
+        self.scopes.remove(&scope).is_some()
+    }
+
+    pub fn disable_all(&mut self) {
+        self.scopes.clear();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.scopes.is_empty()
+    }
+
+    // The notice to reply with if this command is affected by maintenance
+    pub fn check(&self, command : &str) -> Option<&str> {
+        if EXEMPT_COMMANDS.contains(&command) {
+            return None;
+        }
+
+        let mut scopes = vec![MaintenanceScope::Global];
+        if let Some(backend) = MaintenanceScope::for_command(command) {
+            scopes.push(backend);
+        }
+
+        scopes.into_iter()
+            .find_map(|scope| {
+                self.scopes.get(&scope).map(|notice| notice.as_deref().unwrap_or_else(|| scope.default_notice()))
+            })
+    }
+
+    // what we show in our presence, e.g. "wandbox maintenance"
+    pub fn status(&self) -> Option<String> {
+        if self.scopes.contains_key(&MaintenanceScope::Global) {
+            return Some(String::from("maintenance"));
+        }
+        if self.scopes.is_empty() {
+            return None;
+        }
+
+        let mut names: Vec<&str> = self.scopes.keys().map(|s| s.name()).collect();
+        names.sort_unstable();
+        Some(format!("{} maintenance", names.join(" & ")))
+    }
+}
@@ -2,3 +2,11 @@ pub mod constants;
 pub mod discordhelpers;
 pub mod parser;
 pub mod blocklist;
+pub mod langdetect;
+pub mod aliases;
+pub mod votes;
+pub mod announcements;
+pub mod maintenance;
+pub mod ratelimit;
+pub mod shutdown;
+pub mod concurrency;
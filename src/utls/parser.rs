@@ -1,4 +1,7 @@
-use crate::utls::constants::URL_ALLOW_LIST;
+use crate::utls::constants::GODBOLT_HOSTS;
+use crate::apis::godbolt::{ClientState, ClientStateLibrary};
+use crate::utls::langdetect;
+use crate::utls::aliases::AliasTable;
 
 use serenity::model::user::User;
 use serenity::model::channel::Message;
@@ -23,17 +26,18 @@ impl LanguageResolvable for godbolt::Godbolt {
     }
 }
 
-// Allows us to convert some common aliases to other programming languages
-pub fn shortname_to_qualified(language : &str) -> &str {
-    match language {
-        // Replace cpp with c++ since we removed the c pre-processor
-        // support for wandbox. This is okay for godbolt requests, too.
-        "cpp" => "c++",
-        "rs" => "rust",
-        "js" => "javascript",
-        "csharp" => "c#",
-        "py" => "python",
-        _ => language
+// Sets our target from a user-provided name, applying any alias (and the flags it implies)
+fn apply_alias(result : &mut ParserResult, name : &str, aliases : &AliasTable, guild : Option<u64>) {
+    match aliases.resolve(name, guild) {
+        Some(alias) => {
+            result.target = alias.language.clone();
+
+            // implied flags go first so anything the user gave can override them
+            let mut options = alias.options.clone();
+            options.append(&mut result.options);
+            result.options = options;
+        }
+        None => result.target = name.to_owned()
     }
 }
 
@@ -43,10 +47,20 @@ pub struct ParserResult {
     pub target: String,
     pub code: String,
     pub options: Vec<String>,
+    pub libraries: Vec<ClientStateLibrary>,
+    pub guessed_language: bool,
 }
 
-#[allow(clippy::while_let_on_iterator)]
-pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User, target_api : &Arc<RwLock<T>>, reply : &Option<Box<Message>>) -> Result<ParserResult, CommandError> {
+// The snippet given to one of our quick-eval commands, everything after the command's name
+pub fn get_snippet<'a>(content : &'a str, command : &str) -> Result<&'a str, CommandError> {
+    match content.find(' ') {
+        Some(start) => Ok(&content[start..]),
+        None => Err(CommandError::from(format!("Invalid usage. View `;help {}`", command)))
+    }
+}
+
+#[allow(clippy::while_let_on_iterator, clippy::too_many_arguments)]
+pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User, target_api : &Arc<RwLock<T>>, reply : &Option<Box<Message>>, max_attachment_size : u64, attachment_names : &[String], aliases : &AliasTable, guild : Option<u64>, url_allow_list : &[String]) -> Result<ParserResult, CommandError> {
 
     let mut result = ParserResult {
         url: Default::default(),
@@ -54,7 +68,10 @@ pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User,
         target: Default::default(),
         code: Default::default(),
         options: Default::default(),
+        libraries: Default::default(),
+        guessed_language: false,
     };
+    let mut filenames = attachment_names.to_vec();
 
     // we grab the index for the first code block - this will help us
     // know when to stop parsing arguments
@@ -76,10 +93,10 @@ pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User,
         let lang_lookup = target_api.read().await;
         if let Some(param) = args.get(0) {
             let lower_param = param.trim().to_lowercase();
-            let language = shortname_to_qualified(&lower_param);
+            let language = aliases.qualify(&lower_param, guild);
             if lang_lookup.resolve(&language) {
                 args.remove(0);
-                result.target = language.to_owned();
+                apply_alias(&mut result, &lower_param, aliases, guild);
             }
         }
     }
@@ -119,10 +136,17 @@ pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User,
     }
 
     if !result.url.is_empty() {
-        let code = get_url_code(&result.url, author).await?;
-        result.code = code;
+        if let Some(link_id) = get_godbolt_link_id(&result.url) {
+            let state = crate::apis::godbolt::get_client_state(&link_id).await?;
+            let lang_lookup = target_api.read().await;
+            apply_client_state(&mut result, state, &*lang_lookup, aliases, guild)?;
+        }
+        else {
+            let code = get_url_code(&result.url, author, url_allow_list).await?;
+            result.code = code;
+        }
     }
-    else if find_code_block(&mut result, input) {
+    else if find_code_block(&mut result, input, aliases, guild) {
         // If we find a code block from our executor's message, and it's also a reply
         // let's assume we found the stdin and what they're quoting is the code.
         // Anything else probably doesn't make sense.
@@ -130,11 +154,12 @@ pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User,
             result.stdin = result.code;
             result.code = String::default();
 
-            let attachment = get_message_attachment(replied_msg).await?;
-            if !attachment.is_empty() {
-                result.code = attachment;
+            let attachments = get_message_attachments(replied_msg, max_attachment_size).await?;
+            if !attachments.files.is_empty() {
+                result.code = attachments.code();
+                filenames.extend(attachments.filenames());
             }
-            else if !find_code_block(&mut result, &replied_msg.content) {
+            else if !find_code_block(&mut result, &replied_msg.content, aliases, guild) {
                 return Err(CommandError::from(
                     "Cannot find code to compile assuming your code block is the program's stdin.",
                 ))
@@ -145,12 +170,16 @@ pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User,
         // Unable to parse a code block from our executor's message, lets see if we have a
         // reply to grab some code from.
         if let Some(replied_msg) = reply {
-            let attachment = get_message_attachment(replied_msg).await?;
-            if !attachment.is_empty() {
-                result.code = attachment;
+            let attachments = get_message_attachments(replied_msg, max_attachment_size).await?;
+            if !attachments.files.is_empty() {
+                result.code = attachments.code();
+                filenames.extend(attachments.filenames());
+                if result.stdin.is_empty() {
+                    result.stdin = attachments.stdin;
+                }
             }
             // no reply in the attachment, lets check for a code-block..
-            else if !find_code_block(&mut result, &replied_msg.content) {
+            else if !find_code_block(&mut result, &replied_msg.content, aliases, guild) {
                 return Err(CommandError::from(
                     "You must attach a code-block containing code to your message or quote a message that has one.",
                 ))
@@ -163,6 +192,17 @@ pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User,
         }
     }
 
+    // Nothing told us which language this is, so let's take a guess
+    if result.target.is_empty() {
+        let lang_lookup = target_api.read().await;
+        if let Some(guess) = langdetect::detect(&result.code, &filenames) {
+            if guess.is_confident() && lang_lookup.resolve(guess.language) {
+                result.target = guess.language.to_owned();
+                result.guessed_language = true;
+            }
+        }
+    }
+
     if result.target.is_empty() {
         return Err(CommandError::from("You must provide a valid language or compiler!\n\n;compile c++ \n\\`\\`\\`\nint main() {}\n\\`\\`\\`"))
     }
@@ -170,7 +210,7 @@ pub async fn get_components<T : LanguageResolvable>(input: &str, author : &User,
     Ok(result)
 }
 
-async fn get_url_code(url : &str, author : &User) -> Result<String, CommandError> {
+async fn get_url_code(url : &str, author : &User, url_allow_list : &[String]) -> Result<String, CommandError> {
     let url = match reqwest::Url::parse(url) {
         Err(e) => {
             return Err(CommandError::from(format!("Error parsing url: {}", e)))
@@ -184,9 +224,9 @@ async fn get_url_code(url : &str, author : &User) -> Result<String, CommandError
     }
 
     let host_str = host.unwrap().to_string();
-    if !URL_ALLOW_LIST.contains(&host_str.as_str()) {
+    if !url_allow_list.iter().any(|h| *h == host_str) {
         warn!("Blocked URL request to: {} by {} [{}]", host_str, author.id.0, author.tag());
-        return Err(CommandError::from("Unknown paste service. Please use pastebin.com, hastebin.com, or GitHub gists.\n\nAlso please be sure to use a 'raw text' link"))
+        return Err(CommandError::from("Unknown paste service. Please use pastebin.com, hastebin.com, GitHub gists, or a godbolt.org/z/ link.\n\nAlso please be sure to use a 'raw text' link"))
     }
 
     let response = match reqwest::get(url).await {
@@ -204,7 +244,70 @@ async fn get_url_code(url : &str, author : &User) -> Result<String, CommandError
     };
 }
 
-fn find_code_block(result: &mut ParserResult, haystack: &str) -> bool {
+// Compiler Explorer short links look like https://godbolt.org/z/abc123
+fn get_godbolt_link_id(url : &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    if !GODBOLT_HOSTS.contains(&host) {
+        return None;
+    }
+
+    let mut segments = url.path_segments()?;
+    if segments.next()? != "z" {
+        return None;
+    }
+
+    match segments.next() {
+        Some(id) if !id.is_empty() => Some(id.to_owned()),
+        _ => None
+    }
+}
+
+// Fills our result with whatever we could recover from a Compiler Explorer session. Anything
+// the user explicitly gave us (language, options, stdin) takes priority over the link.
+fn apply_client_state<T : LanguageResolvable>(result : &mut ParserResult, state : ClientState, lang_lookup : &T, aliases : &AliasTable, guild : Option<u64>) -> Result<(), CommandError> {
+    let session = match state.sessions.into_iter().next() {
+        Some(s) => s,
+        None => return Err(CommandError::from("That Compiler Explorer link does not contain any source code"))
+    };
+
+    result.code = session.source;
+
+    let mut executors = session.executors.into_iter();
+    let executor = executors.next();
+    let compiler = match session.compilers.into_iter().next() {
+        Some(c) => Some(c),
+        None => executor.as_ref().map(|e| e.compiler.clone())
+    };
+
+    if let Some(executor) = executor {
+        if result.stdin.is_empty() {
+            result.stdin = executor.stdin;
+        }
+    }
+
+    if let Some(compiler) = compiler {
+        if result.target.is_empty() && lang_lookup.resolve(&compiler.id) {
+            result.target = compiler.id.clone();
+        }
+        if result.options.is_empty() {
+            result.options = compiler.options.split_whitespace().map(String::from).collect();
+        }
+        result.libraries = compiler.libs;
+    }
+
+    // the compiler id is godbolt specific, for wandbox we'll settle for the language
+    if result.target.is_empty() {
+        let language = aliases.qualify(&session.language, guild);
+        if lang_lookup.resolve(&language) {
+            result.target = language;
+        }
+    }
+
+    Ok(())
+}
+
+fn find_code_block(result: &mut ParserResult, haystack: &str, aliases : &AliasTable, guild : Option<u64>) -> bool {
     let re = regex::Regex::new(r"```(?:(?P<language>[^\s`]*)\r?\n)?(?P<code>[\s\S]*?)```").unwrap();
     let matches = re.captures_iter(haystack);
 
@@ -236,44 +339,196 @@ fn find_code_block(result: &mut ParserResult, haystack: &str) -> bool {
     // if we still don't have our language target, lets try the language for syntax highlighting
     if result.target.is_empty() {
         if let Some(lang_match) = captures[code_index].name("language") {
-            result.target = shortname_to_qualified(lang_match.as_str()).to_owned();
+            apply_alias(result, lang_match.as_str(), aliases, guild);
         }
     }
 
     true
 }
 
-pub async fn get_message_attachment(msg : &Message) -> Result<String, CommandError> {
-    if !msg.attachments.is_empty() {
-        let attachment = msg.attachments.get(0);
-        if attachment.is_none() {
-            return Ok(String::new());
-        }
-        let attached = attachment.unwrap();
-        if attached.size > 512 * 1024  { // 512 KiB seems enough
-            return Err(CommandError::from(format!("Uploaded file too large: `{} MiB`", attached.size)));
+pub struct MessageAttachments {
+    // (filename, contents) for each source file in upload order
+    pub files: Vec<(String, String)>,
+    // contents of an attachment named input.txt
+    pub stdin: String,
+}
+
+impl MessageAttachments {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.stdin.is_empty()
+    }
+
+    // Multiple source files are handed to the compiler as a single translation unit, in the
+    // order they were uploaded.
+    pub fn code(&self) -> String {
+        self.files
+            .iter()
+            .map(|(_, contents)| contents.trim_end())
+            .collect::<Vec<&str>>()
+            .join("\n\n")
+    }
+
+    pub fn filenames(&self) -> Vec<String> {
+        self.files.iter().map(|(name, _)| name.clone()).collect()
+    }
+}
+
+pub async fn get_message_attachments(msg : &Message, max_size : u64) -> Result<MessageAttachments, CommandError> {
+    let mut result = MessageAttachments {
+        files: Vec::new(),
+        stdin: String::new(),
+    };
+
+    let mut skipped_binary = false;
+    for attached in &msg.attachments {
+        if attached.size > max_size {
+            return Err(CommandError::from(attachment_too_large(&attached.filename, attached.size, max_size)));
         }
-        return match reqwest::get(&attached.url).await {
-            Ok(r) => {
-                let bytes = r.bytes().await.unwrap();
-                let cnt_type = content_inspector::inspect(&bytes);
-                if cnt_type.is_binary() {
-                    return Err(CommandError::from("Invalid file type"));
-                }
 
-                match String::from_utf8(bytes.to_vec()) {
-                    Ok(str) => {
-                        Ok(str)
-                    }
-                    Err(e) => {
-                        Err(CommandError::from(format!("UTF8 Error occured while parsing file: {}", e)))
-                    }
-                }
-            }
+        let bytes = match reqwest::get(&attached.url).await {
+            Ok(r) => match r.bytes().await {
+                Ok(b) => b,
+                Err(e) => return Err(CommandError::from(format!("Failure when downloading attachment: {}", e)))
+            },
             Err(e) => {
-                Err(CommandError::from(format!("Failure when downloading attachment: {}", e)))
+                return Err(CommandError::from(format!("Failure when downloading attachment: {}", e)))
+            }
+        };
+
+        let contents = match decode_attachment(&bytes) {
+            Some(str) => str,
+            None => {
+                // images and the like can be sent alongside code, we'll just ignore them
+                skipped_binary = true;
+                continue;
             }
+        };
+
+        if attached.filename.eq_ignore_ascii_case("input.txt") {
+            result.stdin = contents;
+        }
+        else {
+            result.files.push((attached.filename.clone(), contents));
+        }
+    }
+
+    if result.is_empty() && skipped_binary {
+        return Err(CommandError::from("Invalid file type"));
+    }
+
+    Ok(result)
+}
+
+fn attachment_too_large(filename : &str, size : u64, max_size : u64) -> String {
+    format!(
+        "Uploaded file `{}` too large: `{:.2} KiB` (limit is `{} KiB`)",
+        filename,
+        size as f64 / 1024.0,
+        max_size / 1024
+    )
+}
+
+// Converts raw attachment bytes into a string, returning None for anything that isn't text.
+//
+// Windows editors like to save files as UTF-16 or Windows-1252, so along with the usual UTF-8
+// we'll detect those and transcode them.
+pub fn decode_attachment(bytes : &[u8]) -> Option<String> {
+    if let Some((encoding, bom_len)) = encoding_rs::Encoding::for_bom(bytes) {
+        let (decoded, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return Some(decoded.into_owned());
+    }
+
+    // ASCII text in UTF-16 is also valid UTF-8 (with a NUL between every character), so this
+    // has to be checked first
+    if let Some(encoding) = guess_utf16(bytes) {
+        let (decoded, had_errors) = encoding.decode_without_bom_handling(bytes);
+        if !had_errors {
+            return Some(decoded.into_owned());
         }
     }
-    Ok(String::new())
-}
\ No newline at end of file
+
+    if let Ok(str) = std::str::from_utf8(bytes) {
+        if content_inspector::inspect(bytes).is_binary() {
+            return None;
+        }
+        return Some(str.to_owned());
+    }
+
+    if content_inspector::inspect(bytes).is_binary() {
+        return None;
+    }
+
+    // Latin-1 can decode anything, so this is our last resort
+    let (decoded, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(bytes);
+    Some(decoded.into_owned())
+}
+
+// UTF-16 without a BOM is mostly ASCII text with every other byte being zero
+fn guess_utf16(bytes : &[u8]) -> Option<&'static encoding_rs::Encoding> {
+    if bytes.len() < 2 || bytes.len() % 2 != 0 {
+        return None;
+    }
+
+    let pairs = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+
+    if odd_zeros * 10 >= pairs * 9 && even_zeros * 10 < pairs {
+        Some(encoding_rs::UTF_16LE)
+    }
+    else if even_zeros * 10 >= pairs * 9 && odd_zeros * 10 < pairs {
+        Some(encoding_rs::UTF_16BE)
+    }
+    else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HELLO : &str = "#include <cstdio>\n\nint main() {\n    puts(\"hello\");\n}\n";
+
+    fn fixture(name : &str) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/attachments/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read(&path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path, e))
+    }
+
+    #[test]
+    fn decodes_utf8() {
+        let decoded = decode_attachment(&fixture("utf8.cpp")).unwrap();
+        assert!(decoded.contains("// héllo wörld ✓"));
+        assert!(decoded.starts_with("#include <cstdio>"));
+    }
+
+    #[test]
+    fn decodes_utf16_with_bom() {
+        assert_eq!(decode_attachment(&fixture("utf16le_bom.cpp")).unwrap(), HELLO);
+        assert_eq!(decode_attachment(&fixture("utf16be_bom.cpp")).unwrap(), HELLO);
+    }
+
+    #[test]
+    fn decodes_utf16_without_bom() {
+        assert_eq!(decode_attachment(&fixture("utf16le.cpp")).unwrap(), HELLO);
+        assert_eq!(decode_attachment(&fixture("utf16be.cpp")).unwrap(), HELLO);
+    }
+
+    #[test]
+    fn decodes_latin1() {
+        assert_eq!(decode_attachment(&fixture("latin1.cpp")).unwrap(), "// café\nint main() {}\n");
+    }
+
+    #[test]
+    fn rejects_binary() {
+        assert!(decode_attachment(&fixture("binary.png")).is_none());
+    }
+
+    #[test]
+    fn oversize_message() {
+        assert_eq!(
+            attachment_too_large("main.cpp", 1536, 1024),
+            "Uploaded file `main.cpp` too large: `1.50 KiB` (limit is `1 KiB`)"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::config::RateLimitConfig;
+
+// past this many users we drop anyone whose uses have all expired
+static PRUNE_THRESHOLD: usize = 1000;
+
+/// Our nospam limits. Unlike a framework bucket these are read from the config on every use,
+/// so reloading it applies new limits immediately.
+#[derive(Default)]
+pub struct RateLimiter {
+    users : HashMap<u64, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    // Records a use if the user is within their limits, otherwise returns how long they need to wait
+    pub fn take(&mut self, user : u64, config : &RateLimitConfig) -> Option<Duration> {
+        let now = Instant::now();
+        let delay = Duration::from_secs(config.delay);
+        let time_span = Duration::from_secs(config.time_span);
+
+        if self.users.len() > PRUNE_THRESHOLD {
+            let window = std::cmp::max(delay, time_span);
+            self.users.retain(|_, uses| uses.back().map_or(false, |last| now.duration_since(*last) < window));
+        }
+
+        let uses = self.users.entry(user).or_insert_with(VecDeque::new);
+        while uses.front().map_or(false, |first| now.duration_since(*first) >= time_span) {
+            uses.pop_front();
+        }
+
+        if let Some(last) = uses.back() {
+            let elapsed = now.duration_since(*last);
+            if elapsed < delay {
+                return Some(delay - elapsed);
+            }
+        }
+        if uses.len() >= config.limit {
+            if let Some(first) = uses.front() {
+                return Some(time_span - now.duration_since(*first));
+            }
+        }
+
+        uses.push_back(now);
+        None
+    }
+}
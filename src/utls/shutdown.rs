@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::RwLock;
+use serenity::prelude::TypeMap;
+
+use crate::cache::{BlocklistCache, ShardManagerCache, ShutdownCache, StatsDatabaseCache, StatsManagerCache};
+
+// how long we'll wait on commands that are still running
+static DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+// how long the stats queue gets to send what it has before it spills to disk
+static STATS_TIMEOUT: Duration = Duration::from_secs(10);
+// how long the stats database gets to write what's still queued
+static DATABASE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether we're shutting down, and how many commands we're still finishing
+#[derive(Default)]
+pub struct Shutdown {
+    stopping : AtomicBool,
+    in_flight : AtomicUsize,
+}
+
+impl Shutdown {
+    // Returns false if we're shutting down, otherwise the command counts as in flight until end()
+    pub fn begin(&self) -> bool {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if self.stopping.load(Ordering::SeqCst) {
+            self.end();
+            return false;
+        }
+        true
+    }
+
+    pub fn end(&self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    // Stops new commands, then waits for the ones in flight. Returns false if we gave up on them.
+    async fn drain(&self, timeout : Duration) -> bool {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.in_flight() > 0 {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        true
+    }
+}
+
+// Shuts down gracefully on SIGTERM or ctrl+c, a second signal exits immediately
+pub fn spawn_signal_handler(data : Arc<RwLock<TypeMap>>) {
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutting down, press ctrl+c again to exit immediately");
+
+        tokio::spawn(async {
+            wait_for_signal().await;
+            warn!("Exiting without finishing in-flight commands");
+            std::process::exit(1);
+        });
+
+        shutdown(data).await;
+    });
+}
+
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => (),
+                    _ = terminate.recv() => (),
+                }
+                return;
+            }
+            Err(e) => warn!("Unable to listen for SIGTERM: {}", e),
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn shutdown(data : Arc<RwLock<TypeMap>>) {
+    let data = data.read().await;
+
+    let shutdown = data.get::<ShutdownCache>().unwrap();
+    if !shutdown.drain(DRAIN_TIMEOUT).await {
+        warn!("Gave up waiting on {} in-flight commands", shutdown.in_flight());
+    }
+
+    // whatever the stats api doesn't take in time is spilled to disk for next time
+    let queue = data.get::<StatsManagerCache>().unwrap().lock().await.take_queue();
+    if let Some(queue) = queue {
+        if tokio::time::timeout(STATS_TIMEOUT, queue.close()).await.is_err() {
+            warn!("Timed out flushing stats");
+        }
+    }
+
+    if let Some(db) = data.get::<StatsDatabaseCache>() {
+        let writer = db.lock().await.close_writer();
+        if let Some(writer) = writer {
+            match tokio::time::timeout(DATABASE_TIMEOUT, tokio::task::spawn_blocking(move || writer.join())).await {
+                Ok(Ok(Ok(()))) => (),
+                Ok(_) => warn!("Stats database writer panicked"),
+                Err(_) => warn!("Timed out writing statistics"),
+            }
+        }
+    }
+
+    data.get::<BlocklistCache>().unwrap().read().await.write();
+
+    let mut shard_manager = data.get::<ShardManagerCache>().unwrap().lock().await;
+    shard_manager.shutdown_all().await;
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+
+use serde::*;
+
+use crate::config::VoteConfig;
+
+// two votes this close together keep a streak going, top.gg lets you vote every 12 hours
+static STREAK_WINDOW: i64 = 60 * 60 * 48;
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct VoteRecord {
+    pub last_vote : i64,
+    pub streak : u32,
+    pub total : u32,
+}
+
+/// What recent voters get, see VoteConfig
+#[derive(Default)]
+pub struct VotePerks {
+    // how long perks last after a vote, 0 disables them
+    pub duration : i64,
+    pub skip_ratelimit : bool,
+    pub output_limit : usize,
+    // in bytes
+    pub attachment_limit : u64,
+}
+
+impl From<&VoteConfig> for VotePerks {
+    fn from(config : &VoteConfig) -> VotePerks {
+        VotePerks {
+            duration: config.perk_hours as i64 * 60 * 60,
+            skip_ratelimit: config.skip_ratelimit,
+            output_limit: config.output_len,
+            attachment_limit: config.attachment_limit * 1024,
+        }
+    }
+}
+
+/// Everyone who's voted for us, loaded from votes.json
+#[derive(Serialize, Deserialize)]
+pub struct VoteTracker {
+    users : HashMap<String, VoteRecord>,
+    #[serde(skip)]
+    pub perks : VotePerks,
+    #[serde(skip)]
+    path : String,
+}
+
+impl VoteTracker {
+    pub fn new(perks : VotePerks) -> VoteTracker {
+        VoteTracker::load("votes.json", perks)
+    }
+
+    pub fn load(path : &str, perks : VotePerks) -> VoteTracker {
+        if !std::path::Path::new(path).exists() {
+            return VoteTracker::create_votes(path, perks);
+        }
+
+        let json = fs::read_to_string(path)
+            .expect("Unable to read votes.json");
+
+        let mut votes : VoteTracker = serde_json::from_str(&json)
+            .expect("Unable to deserialize votes.json");
+        votes.perks = perks;
+        votes.path = String::from(path);
+        votes
+    }
+
+    pub fn record(&mut self, user : u64) -> VoteRecord {
+        let now = chrono::Utc::now().timestamp();
+        let record = self.users.entry(user.to_string()).or_default();
+
+        if now - record.last_vote <= STREAK_WINDOW {
+            // voting on a second site the same day doesn't extend a streak
+            if now - record.last_vote >= STREAK_WINDOW / 4 {
+                record.streak += 1;
+            }
+        }
+        else {
+            record.streak = 1;
+        }
+        record.total += 1;
+        record.last_vote = now;
+
+        let record = record.clone();
+        self.write();
+        record
+    }
+
+    pub fn get(&self, user : u64) -> Option<&VoteRecord> {
+        self.users.get(&user.to_string())
+    }
+
+    // seconds of perks this user has left
+    pub fn perks_remaining(&self, user : u64) -> i64 {
+        match self.get(user) {
+            Some(record) => {
+                let remaining = record.last_vote + self.perks.duration - chrono::Utc::now().timestamp();
+                std::cmp::max(remaining, 0)
+            }
+            None => 0
+        }
+    }
+
+    pub fn has_perks(&self, user : u64) -> bool {
+        self.perks_remaining(user) > 0
+    }
+
+    pub fn output_limit(&self, user : u64, default : usize) -> usize {
+        if self.has_perks(user) {
+            std::cmp::max(self.perks.output_limit, default)
+        } else {
+            default
+        }
+    }
+
+    pub fn attachment_limit(&self, user : u64, default : u64) -> u64 {
+        if self.has_perks(user) {
+            std::cmp::max(self.perks.attachment_limit, default)
+        } else {
+            default
+        }
+    }
+
+    pub fn write(&self) {
+        let json = serde_json::to_string(self)
+            .expect("Unable to serialize votes.json");
+
+        fs::write(&self.path, json)
+            .expect("Unable to create votes.json!");
+    }
+
+    fn create_votes(path : &str, perks : VotePerks) -> VoteTracker {
+        let votes = VoteTracker {
+            users : Default::default(),
+            perks,
+            path : String::from(path)
+        };
+        votes.write();
+        votes
+    }
+}
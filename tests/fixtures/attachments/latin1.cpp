@@ -0,0 +1,2 @@
+// caf
+int main() {}
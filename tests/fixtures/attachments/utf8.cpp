@@ -0,0 +1,6 @@
+#include <cstdio>
+
+// héllo wörld ✓
+int main() {
+    puts("hello");
+}